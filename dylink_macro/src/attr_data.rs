@@ -1,19 +1,124 @@
-use proc_macro2::Span;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::ToTokens;
 use syn::punctuated::Punctuated;
 use syn::{spanned::Spanned, *};
 
+use crate::mangle::Params;
+
+/// Where a function resolves its symbol from.
+pub enum Library {
+	/// `library = <expr>`, evaluating to a `SymbolSource`.
+	Source(Expr),
+	/// `loader = <expr>`, evaluating to a function resolving symbols by name.
+	Loader(Expr),
+}
+
+impl Library {
+	/// The argument as written, for diagnostics.
+	pub fn name(&self) -> String {
+		let (Self::Source(expr) | Self::Loader(expr)) = self;
+		expr.to_token_stream().to_string().replace(' ', "")
+	}
+}
+
+/// The case of derived link names, as set by `case = "..."`.
+#[derive(Clone, Copy)]
+pub enum Case {
+	/// `createInstance`, or `vkCreateInstance` after a prefix.
+	Camel,
+	/// `CreateInstance`
+	Pascal,
+	/// `create_instance`
+	Snake,
+	/// `CREATE_INSTANCE`
+	ScreamingSnake,
+}
+
+impl Case {
+	const EXPECTED: &'static str =
+		"Expected `\"camel\"`, `\"pascal\"`, `\"snake\"`, or `\"screaming_snake\"`.";
+
+	fn parse(value: &str) -> Option<Self> {
+		match value {
+			"camel" => Some(Self::Camel),
+			"pascal" => Some(Self::Pascal),
+			"snake" => Some(Self::Snake),
+			"screaming_snake" => Some(Self::ScreamingSnake),
+			_ => None,
+		}
+	}
+
+	/// Converts a snake case name. Words following a prefix are treated as if they weren't first.
+	fn apply(self, name: &str, after_prefix: bool) -> String {
+		let words = name.split('_').filter(|word| !word.is_empty());
+		match self {
+			Self::Camel | Self::Pascal => words
+				.enumerate()
+				.map(|(i, word)| {
+					let mut chars = word.chars();
+					let first = chars.next().unwrap_or_default();
+					if i == 0 && !after_prefix && matches!(self, Self::Camel) {
+						word.to_string()
+					} else {
+						first.to_uppercase().chain(chars).collect()
+					}
+				})
+				.collect(),
+			Self::Snake => words.map(str::to_lowercase).collect::<Vec<_>>().join("_"),
+			Self::ScreamingSnake => words.map(str::to_uppercase).collect::<Vec<_>>().join("_"),
+		}
+	}
+}
+
+/// How link names are derived from function names, as set by `prefix`, `suffix`, and `case`.
+#[derive(Default)]
+pub struct NameMapping {
+	pub prefix: Option<String>,
+	pub suffix: Option<String>,
+	pub case: Option<Case>,
+}
+
+impl NameMapping {
+	/// Derives the link name of a function.
+	pub fn apply(&self, name: &str) -> String {
+		let prefix = self.prefix.as_deref().unwrap_or_default();
+		let suffix = self.suffix.as_deref().unwrap_or_default();
+		let name = match self.case {
+			Some(case) => case.apply(name, !prefix.is_empty()),
+			None => name.to_string(),
+		};
+		format!("{prefix}{name}{suffix}")
+	}
+}
+
 pub struct AttrData {
-	pub library: std::result::Result<syn::Path, Span>,
-	pub link_name: Option<(String, Span)>,
+	pub library: std::result::Result<Library, Span>,
+	pub link_name: Option<(Vec<String>, Span)>,
+	/// The parameters of the declaration given to `cpp`, which also sets `link_name`.
+	pub cpp: Option<(Params, Span)>,
+	pub try_fn: Option<Span>,
+	pub trace: Option<Span>,
+	pub fallback: Option<(syn::Path, Span)>,
+	pub mapping: NameMapping,
+	/// The predicate of `static_link = cfg(<predicate>)`.
+	pub static_link: Option<TokenStream2>,
+	pub static_lib: Option<String>,
 }
 
 impl TryFrom<Punctuated<Expr, Token!(,)>> for AttrData {
 	type Error = syn::Error;
 	fn try_from(value: Punctuated<Expr, Token!(,)>) -> Result<Self> {
-		let mut maybe_library: Option<syn::Path> = None;
-		let mut link_name: Option<(String, Span)> = None;
+		let mut maybe_library: Option<Library> = None;
+		let mut link_name: Option<(Vec<String>, Span)> = None;
+		let mut cpp: Option<(Params, Span)> = None;
+		let mut try_fn: Option<Span> = None;
+		let mut trace: Option<Span> = None;
+		let mut fallback: Option<(syn::Path, Span)> = None;
+		let mut mapping = NameMapping::default();
+		let mut static_link: Option<TokenStream2> = None;
+		let mut static_lib: Option<String> = None;
 		let mut errors = vec![];
-		const EXPECTED_KW: &str = "Expected `library`, or `link_name`.";
+		const EXPECTED_KW: &str = "Expected `library`, `loader`, `link_name`, `cpp`, `try_fn`, `trace`, `fallback`, `prefix`, `suffix`, `case`, `static_link`, or `static_lib`.";
 
 		for expr in value.iter() {
 			match expr {
@@ -23,51 +128,141 @@ impl TryFrom<Punctuated<Expr, Token!(,)>> for AttrData {
 					let Expr::Path(ExprPath { path, .. }) = assign_left else {
 						unreachable!("internal error when parsing Expr::Assign");
 					};
-					if path.is_ident("library") {
-						// Branch for syntax: #[dylink(library = <path>)]
+					if path.is_ident("library") || path.is_ident("loader") {
+						// Branch for syntax: #[dylink(library = <expr>)]
+						// or: #[dylink(loader = <expr>)]
+						if maybe_library.is_none() {
+							maybe_library = Some(if path.is_ident("library") {
+								Library::Source(assign_right.clone())
+							} else {
+								Library::Loader(assign_right.clone())
+							});
+						} else {
+							errors.push(Error::new(assign.span(), "library is already defined"));
+						}
+					} else if path.is_ident("link_name") {
+						// Branch for syntax: #[dylink(link_name = <string>)]
+						// or: #[dylink(link_name = [<string>, ...])]
+						match parse_link_names(assign_right) {
+							Ok(names) => {
+								if link_name.is_none() {
+									link_name = Some((names, assign.span()));
+								} else {
+									errors.push(Error::new(
+										assign.span(),
+										"linker is already defined",
+									));
+								}
+							}
+							Err(e) => errors.push(e),
+						}
+					} else if path.is_ident("cpp") {
+						// Branch for syntax: #[dylink(cpp = <string>)]
+						parse_cpp(assign, &mut link_name, &mut cpp, &mut errors);
+					} else if path.is_ident("fallback") {
+						// Branch for syntax: #[dylink(fallback = <path>)]
+						parse_fallback(assign, &mut fallback, &mut errors);
+					} else if path.is_ident("prefix") || path.is_ident("suffix") {
+						// Branch for syntax: #[dylink(prefix = <string>)]
+						// or: #[dylink(suffix = <string>)]
+						let field = if path.is_ident("prefix") {
+							&mut mapping.prefix
+						} else {
+							&mut mapping.suffix
+						};
 						match assign_right {
-							Expr::Path(ExprPath { path, .. }) => {
-								if maybe_library.is_none() {
-									maybe_library = Some(path.clone());
+							Expr::Lit(ExprLit {
+								lit: Lit::Str(val), ..
+							}) => {
+								if field.is_none() {
+									*field = Some(val.value());
 								} else {
 									errors.push(Error::new(
 										assign.span(),
-										"library is already defined",
+										format!("{} is already defined", path.to_token_stream()),
 									));
 								}
 							}
-							right => errors.push(Error::new(right.span(), "Expected identifier.")),
+							right => errors.push(Error::new(right.span(), "Expected string.")),
 						}
-					} else if path.is_ident("link_name") {
-						// Branch for syntax: #[dylink(link_name = <string>)]
+					} else if path.is_ident("static_link") {
+						// Branch for syntax: #[dylink(static_link = cfg(<predicate>))]
+						match parse_cfg(assign_right) {
+							Some(_) if static_link.is_some() => errors
+								.push(Error::new(assign.span(), "static_link is already defined")),
+							Some(predicate) => static_link = Some(predicate),
+							None => errors.push(Error::new(
+								assign_right.span(),
+								"Expected `cfg(<predicate>)`.",
+							)),
+						}
+					} else if path.is_ident("static_lib") {
+						// Branch for syntax: #[dylink(static_lib = <string>)]
 						match assign_right {
 							Expr::Lit(ExprLit {
 								lit: Lit::Str(val), ..
 							}) => {
-								if link_name.is_none() {
-									link_name = Some((val.value(), assign.span()));
+								if static_lib.is_none() {
+									static_lib = Some(val.value());
 								} else {
 									errors.push(Error::new(
 										assign.span(),
-										"linker is already defined",
+										"static_lib is already defined",
 									));
 								}
 							}
 							right => errors.push(Error::new(right.span(), "Expected string.")),
 						}
+					} else if path.is_ident("case") {
+						// Branch for syntax: #[dylink(case = <string>)]
+						match assign_right {
+							Expr::Lit(ExprLit {
+								lit: Lit::Str(val), ..
+							}) => match Case::parse(&val.value()) {
+								Some(_) if mapping.case.is_some() => errors
+									.push(Error::new(assign.span(), "case is already defined")),
+								Some(case) => mapping.case = Some(case),
+								None => errors.push(Error::new(val.span(), Case::EXPECTED)),
+							},
+							right => errors.push(Error::new(right.span(), Case::EXPECTED)),
+						}
 					} else {
 						errors.push(Error::new(assign_left.span(), EXPECTED_KW));
 					}
 				}
 
+				// Branch for syntax: #[dylink(try_fn)]
+				Expr::Path(ExprPath { path, .. }) if path.is_ident("try_fn") => {
+					if try_fn.is_none() {
+						try_fn = Some(path.span());
+					} else {
+						errors.push(Error::new(path.span(), "try_fn is already defined"));
+					}
+				}
+
+				// Branch for syntax: #[dylink(trace)]
+				Expr::Path(ExprPath { path, .. }) if path.is_ident("trace") => {
+					if trace.is_none() {
+						trace = Some(path.span());
+					} else {
+						errors.push(Error::new(path.span(), "trace is already defined"));
+					}
+				}
+
 				// Branch for everything else.
 				expr => errors.push(Error::new(expr.span(), EXPECTED_KW)),
 			}
 		}
+		if static_lib.is_some() && static_link.is_none() {
+			errors.push(Error::new(
+				value.span(),
+				"`static_lib` requires `static_link = cfg(<predicate>)`.",
+			));
+		}
 		if maybe_library.is_none() {
 			errors.push(Error::new(
 				value.span(),
-				"No library detected. Suggest using: `library = <path>`.",
+				"No library detected. Suggest using: `library = <expr>` or `loader = <expr>`.",
 			));
 		}
 
@@ -86,7 +281,149 @@ impl TryFrom<Punctuated<Expr, Token!(,)>> for AttrData {
 			Ok(Self {
 				library: maybe_library.ok_or(value.span()),
 				link_name,
+				cpp,
+				try_fn,
+				trace,
+				fallback,
+				mapping,
+				static_link,
+				static_lib,
 			})
 		}
 	}
 }
+
+/// Arguments of a `#[dylink(...)]` attribute applied to an item of a foreign module.
+#[derive(Default)]
+pub struct ItemAttrData {
+	pub library: Option<Library>,
+	pub link_name: Option<(Vec<String>, Span)>,
+	pub cpp: Option<(Params, Span)>,
+	pub fallback: Option<(syn::Path, Span)>,
+}
+
+impl TryFrom<Punctuated<Expr, Token!(,)>> for ItemAttrData {
+	type Error = syn::Error;
+	fn try_from(value: Punctuated<Expr, Token!(,)>) -> Result<Self> {
+		let mut data = Self::default();
+		let mut errors = vec![];
+		const EXPECTED_KW: &str =
+			"Expected `library`, `loader`, `link_name`, `cpp`, or `fallback`.";
+
+		for expr in value.iter() {
+			match expr {
+				Expr::Assign(assign) => {
+					let Expr::Path(ExprPath { path, .. }) = assign.left.as_ref() else {
+						unreachable!("internal error when parsing Expr::Assign");
+					};
+					if path.is_ident("library") || path.is_ident("loader") {
+						if data.library.is_none() {
+							data.library = Some(if path.is_ident("library") {
+								Library::Source(assign.right.as_ref().clone())
+							} else {
+								Library::Loader(assign.right.as_ref().clone())
+							});
+						} else {
+							errors.push(Error::new(assign.span(), "library is already defined"));
+						}
+					} else if path.is_ident("link_name") {
+						match parse_link_names(&assign.right) {
+							Ok(_) if data.link_name.is_some() => {
+								errors.push(Error::new(assign.span(), "linker is already defined"))
+							}
+							Ok(names) => data.link_name = Some((names, assign.span())),
+							Err(e) => errors.push(e),
+						}
+					} else if path.is_ident("cpp") {
+						parse_cpp(assign, &mut data.link_name, &mut data.cpp, &mut errors);
+					} else if path.is_ident("fallback") {
+						parse_fallback(assign, &mut data.fallback, &mut errors);
+					} else {
+						errors.push(Error::new(assign.left.span(), EXPECTED_KW));
+					}
+				}
+				expr => errors.push(Error::new(expr.span(), EXPECTED_KW)),
+			}
+		}
+
+		match errors.into_iter().reduce(|mut main_err, err| {
+			main_err.combine(err);
+			main_err
+		}) {
+			Some(err) => Err(err),
+			None => Ok(data),
+		}
+	}
+}
+
+fn parse_link_names(expr: &Expr) -> Result<Vec<String>> {
+	let as_str = |expr: &Expr| match expr {
+		Expr::Lit(ExprLit {
+			lit: Lit::Str(val), ..
+		}) => Ok(val.value()),
+		expr => Err(Error::new(expr.span(), "Expected string.")),
+	};
+	match expr {
+		Expr::Array(array) if array.elems.is_empty() => {
+			Err(Error::new(array.span(), "Expected at least one string."))
+		}
+		Expr::Array(array) => array.elems.iter().map(as_str).collect(),
+		expr => as_str(expr).map(|name| vec![name]),
+	}
+}
+
+// Branch for syntax: #[dylink(cpp = <string>)]
+// The declaration is mangled into the link name.
+fn parse_cpp(
+	assign: &ExprAssign,
+	link_name: &mut Option<(Vec<String>, Span)>,
+	cpp: &mut Option<(Params, Span)>,
+	errors: &mut Vec<Error>,
+) {
+	match assign.right.as_ref() {
+		Expr::Lit(ExprLit {
+			lit: Lit::Str(val), ..
+		}) => match crate::mangle::mangle(&val.value()) {
+			Ok(_) if link_name.is_some() => {
+				errors.push(Error::new(assign.span(), "linker is already defined"))
+			}
+			Ok((name, params)) => {
+				*link_name = Some((vec![name], assign.span()));
+				*cpp = Some((params, assign.span()));
+			}
+			Err(e) => errors.push(Error::new(val.span(), format!("Invalid declaration: {e}."))),
+		},
+		right => errors.push(Error::new(right.span(), "Expected string.")),
+	}
+}
+
+/// Returns the predicate of `cfg(<predicate>)`.
+fn parse_cfg(expr: &Expr) -> Option<TokenStream2> {
+	let Expr::Call(ExprCall { func, args, .. }) = expr else {
+		return None;
+	};
+	match func.as_ref() {
+		Expr::Path(ExprPath { path, .. }) if path.is_ident("cfg") && args.len() == 1 => {
+			Some(args.to_token_stream())
+		}
+		_ => None,
+	}
+}
+
+// Branch for syntax: #[dylink(fallback = <path>)]
+fn parse_fallback(
+	assign: &ExprAssign,
+	fallback: &mut Option<(syn::Path, Span)>,
+	errors: &mut Vec<Error>,
+) {
+	match assign.right.as_ref() {
+		Expr::Path(ExprPath { path, .. }) => {
+			if fallback.is_none() {
+				*fallback = Some((path.clone(), assign.span()));
+			} else {
+				errors.push(Error::new(assign.span(), "fallback is already defined"));
+			}
+		}
+		right => errors.push(Error::new(right.span(), "Expected path.")),
+	}
+}
@@ -1,6 +1,7 @@
 #![cfg_attr(feature = "warnings", feature(proc_macro_diagnostic))]
 
 mod attr_data;
+mod mangle;
 
 use proc_macro::TokenStream as TokenStream1;
 use proc_macro2::TokenStream as TokenStream2;
@@ -8,10 +9,102 @@ use quote::*;
 use syn::{parse::Parser, punctuated::Punctuated, spanned::Spanned, Expr, Token};
 
 use attr_data::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use syn::ForeignItem;
 
+// counts expansions, to give the thunks shared by a function and its accessors unique keys.
+static EXPANSIONS: AtomicUsize = AtomicUsize::new(0);
+
 /// Macro for generating shared symbol thunks procedurally.
 ///
+/// `library` may be any expression evaluating to a `dylink::SymbolSource` with a `'static`
+/// lifetime, which is usually a `LibLock`, such as `library = VULKAN`, `library = libs::vulkan()`,
+/// or `library = *LAZY_LIB`. Alternatively, `loader` may be a function of type
+/// `fn(&CStr) -> *const c_void`, which resolves symbols itself, such as `vkGetInstanceProcAddr`
+/// or `glXGetProcAddress`.
+///
+/// Temporaries are rejected, since the library would be closed while its symbols are still cached:
+///
+///```compile_fail
+/// use dylink::*;
+///
+/// #[dylink(library = sync::LibLock::new(&["libc.so.6"]))]
+/// extern "C" {
+///     fn atoi(s: *const std::ffi::c_char) -> i32;
+/// }
+///```
+///
+/// For every function `foo`, `foo_is_available()` and `foo_ptr()` are generated next to it to
+/// query the symbol without calling the function, so they're associated functions when `foo` is
+/// declared in an `impl` block. Items of foreign modules also get a `PFN_foo` type alias of the
+/// function pointer type. These are prefixed with the name of the function, so they don't
+/// collide with types of the same name, such as `struct stat`.
+///
+/// Generated functions panic if their symbol cannot be loaded. Passing `try_fn` additionally
+/// generates a `try_` prefixed function for each function, which returns a
+/// `Result<_, dylink::Error>` instead.
+///
+/// `fallback = path::to::function` installs a Rust function with the same signature in place of
+/// the symbol, if it cannot be loaded. Items of foreign modules may be given their own fallback
+/// with `#[dylink(fallback = ...)]`.
+///
+/// `link_name` accepts either a single name, or an array of names that are tried in order, such
+/// as `link_name = ["glBindVertexArray", "glBindVertexArrayARB"]`.
+///
+/// `trace` reports the resolution and calls of each function to the `dylink::hooks` subscriber.
+/// The `trace` feature enables this for every function.
+///
+/// With the `testing` feature, `foo_set_override`, `foo_clear_override`, and a scoped
+/// `foo_override_with` are generated too, which replace the function so tests can inject fakes.
+///
+/// Variadic functions, such as `printf`, can't be defined on stable Rust. Instead, a function of the
+/// same name without parameters is generated, which resolves the symbol and returns the function
+/// pointer, so `printf` is called as `printf()(format, ...)`. The pointer is tracked as a call
+/// through its library until the end of the statement, so a reload waits for the call to return,
+/// but must not be kept any longer. Calls to variadic functions aren't traced.
+///
+/// Link names can be derived from function names with `prefix`, `suffix`, and `case`, which
+/// are applied to every function without a `link_name`. `case` may be `"camel"`, `"pascal"`,
+/// `"snake"`, or `"screaming_snake"`, and converts the snake case function name before the prefix
+/// and suffix are added. With a prefix, camel case capitalizes the first word, so `prefix = "vk",
+/// case = "camel"` maps `create_instance` to `vkCreateInstance`.
+///
+/// Items of foreign modules may override `library`, `loader`, `link_name`, and `fallback` with
+/// their own `#[dylink(...)]` attribute. The standard `#[link_name = "..."]` attribute is also
+/// honored.
+///
+/// Functions declared `safe` in an `unsafe extern` block are generated as safe functions.
+///
+/// `static_link = cfg(<predicate>)` declares the functions in a plain `extern` block instead,
+/// when the predicate holds, so the same declarations serve static and dynamic builds.
+/// `static_lib = "name"` adds `#[link(name = "name")]` to that block. Static builds keep the
+/// accessors and `try_` functions, which then can't fail: `foo_is_available()` returns `true`,
+/// `foo_ptr()` returns the linked function, and overrides still replace it.
+///
+/// `cpp = "ns::Widget::resize(int, float)"` resolves a C++ function by its Itanium mangled name,
+/// which is computed from the declaration at compile time. Free functions and member functions
+/// taking builtin types, named types, pointers, and references are supported. Fixed width
+/// integer typedefs and `size_t` are mangled as on 64-bit targets, while `int64_t` and
+/// `uint64_t` are rejected, since their underlying types differ between targets. `std::string`
+/// and the iostreams use their standard abbreviations, which don't match libraries built with
+/// libstdc++'s C++11 ABI or libc++. Member functions take `this` as their first parameter, and
+/// the parameters must match the declaration:
+///
+///```compile_fail
+/// use dylink::*;
+///
+/// static LIB: MockLibrary = MockLibrary::new();
+///
+/// #[dylink(library = LIB)]
+/// extern "C" {
+///     #[dylink(cpp = "ns::Widget::resize(int, float) const")]
+///     fn resize(width: i32, scale: f32);
+/// }
+///```
+///
+/// Every generated function registers itself with its library at startup, so that
+/// `LibLock::preload_declared` can resolve all of them ahead of their first call.
+///
 /// May currently be used in 2 patterns:
 /// * foreign modules
 /// * foreign functions
@@ -48,26 +141,48 @@ pub fn dylink(args: TokenStream1, input: TokenStream1) -> TokenStream1 {
 				if let Some((_, span)) = attr_data.link_name {
 					return syn::Error::new(
 						span,
-						"`link_name` should be applied to a foreign function",
+						"`link_name` and `cpp` should be applied to a foreign function, or an item with `#[dylink(...)]`",
+					)
+					.to_compile_error()
+					.into();
+				}
+				if let Some((_, span)) = attr_data.fallback {
+					return syn::Error::new(
+						span,
+						"`fallback` should be applied to a foreign function, or an item with `#[dylink(fallback = ...)]`",
 					)
 					.to_compile_error()
 					.into();
 				}
 
 				let abi = &foreign_mod.abi;
+				let unsafety = &foreign_mod.unsafety;
 				foreign_mod
 					.items
 					.iter()
-					.map(|item| match item {
+					.map(|item| {
+						match item {
 						ForeignItem::Fn(fn_item) => {
-							parse_fn::<true>(Some(abi), fn_item, &attr_data)
+							parse_fn::<true>(Some(abi), fn_item, &attr_data, false)
 						}
-						other => quote!(#abi {#other}),
+						// `safe fn` items aren't supported by syn, so they're parsed here.
+						ForeignItem::Verbatim(tokens) => match parse_safe_fn(tokens.clone()) {
+							Some(fn_item) if unsafety.is_none() => syn::Error::new(
+								fn_item.span(),
+								"items in `extern` blocks without `unsafe` cannot be declared `safe`",
+							)
+							.into_compile_error(),
+							Some(fn_item) => parse_fn::<true>(Some(abi), &fn_item, &attr_data, true),
+							None => quote!(#unsafety #abi {#tokens}),
+						},
+						other => quote!(#unsafety #abi {#other}),
+					}
 					})
 					.collect::<TokenStream2>()
 					.into()
 			} else if let Ok(foreign_fn) = syn::parse2::<syn::ForeignItemFn>(input.into()) {
-				parse_fn::<false>(foreign_fn.sig.abi.as_ref(), &foreign_fn, &attr_data).into()
+				parse_fn::<false>(foreign_fn.sig.abi.as_ref(), &foreign_fn, &attr_data, false)
+					.into()
 			} else {
 				panic!("failed to parse");
 			}
@@ -76,22 +191,38 @@ pub fn dylink(args: TokenStream1, input: TokenStream1) -> TokenStream1 {
 	}
 }
 
+/// Parses a `safe fn` item of an `unsafe extern` block, returning `None` for anything else.
+fn parse_safe_fn(tokens: TokenStream2) -> Option<syn::ForeignItemFn> {
+	Parser::parse2(
+		|input: syn::parse::ParseStream| {
+			let attrs = input.call(syn::Attribute::parse_outer)?;
+			let vis: syn::Visibility = input.parse()?;
+			let safe: syn::Ident = input.parse()?;
+			if safe != "safe" {
+				return Err(syn::Error::new(safe.span(), "expected `safe`"));
+			}
+			Ok(syn::ForeignItemFn {
+				attrs,
+				vis,
+				sig: input.parse()?,
+				semi_token: input.parse()?,
+			})
+		},
+		tokens,
+	)
+	.ok()
+}
+
 fn parse_fn<const IS_MOD_ITEM: bool>(
 	abi: Option<&syn::Abi>,
 	fn_item: &syn::ForeignItemFn,
 	attr_data: &AttrData,
+	safe: bool,
 ) -> TokenStream2 {
 	let abi = abi.to_token_stream();
 	let fn_name = fn_item.sig.ident.to_token_stream();
 	let vis = fn_item.vis.to_token_stream();
 	let output = fn_item.sig.output.to_token_stream();
-	let library = match attr_data.library {
-		Ok(ref path) => path,
-		Err(span) => {
-			return syn::Error::new(span, "`link_name` should be applied to a foreign function")
-				.to_compile_error()
-		}
-	};
 	// constness makes no sense in this context
 	match &fn_item.sig.constness {
 		None => (),
@@ -101,11 +232,64 @@ fn parse_fn<const IS_MOD_ITEM: bool>(
 		}
 	}
 
-	let fn_attrs: Vec<TokenStream2> = fn_item
-		.attrs
-		.iter()
-		.map(syn::Attribute::to_token_stream)
-		.collect();
+	// items of foreign modules may carry their own `#[dylink(...)]` attributes, which take
+	// precedence over the attribute of the module. `#[link_name = "..."]` is honored too.
+	let mut item_data = ItemAttrData::default();
+	let mut fn_attrs: Vec<&syn::Attribute> = Vec::new();
+	for attr in &fn_item.attrs {
+		if IS_MOD_ITEM && attr.path().is_ident("dylink") {
+			let parsed = attr
+				.parse_args_with(Punctuated::<Expr, Token!(,)>::parse_terminated)
+				.and_then(ItemAttrData::try_from);
+			match parsed {
+				Ok(parsed) => {
+					item_data.library = parsed.library.or(item_data.library);
+					item_data.fallback = parsed.fallback.or(item_data.fallback);
+					if let Some((names, span)) = parsed.link_name {
+						if item_data.link_name.is_some() {
+							return syn::Error::new(span, "linker is already defined")
+								.into_compile_error();
+						}
+						item_data.link_name = Some((names, span));
+						item_data.cpp = parsed.cpp;
+					}
+				}
+				Err(e) => return e.into_compile_error(),
+			}
+		} else if attr.path().is_ident("link_name") {
+			let name = match &attr.meta {
+				syn::Meta::NameValue(syn::MetaNameValue {
+					value: Expr::Lit(syn::ExprLit {
+						lit: syn::Lit::Str(val),
+						..
+					}),
+					..
+				}) => val.value(),
+				meta => {
+					return syn::Error::new(meta.span(), "Expected `#[link_name = \"...\"]`.")
+						.into_compile_error()
+				}
+			};
+			if item_data.link_name.is_some() || attr_data.link_name.is_some() {
+				return syn::Error::new(attr.span(), "linker is already defined")
+					.into_compile_error();
+			}
+			item_data.link_name = Some((vec![name], attr.span()));
+		} else {
+			fn_attrs.push(attr);
+		}
+	}
+	let fallback = item_data
+		.fallback
+		.as_ref()
+		.or(attr_data.fallback.as_ref())
+		.map(|(path, _)| path);
+	let library = match (&item_data.library, &attr_data.library) {
+		(Some(library), _) | (None, Ok(library)) => library,
+		(None, Err(span)) => {
+			return syn::Error::new(*span, "No library detected.").to_compile_error()
+		}
+	};
 
 	// `self` can be used, but not inferred, so it's conditionally useful.
 	if let syn::ReturnType::Type(_, ret_type) = &fn_item.sig.output {
@@ -123,7 +307,6 @@ fn parse_fn<const IS_MOD_ITEM: bool>(
 	let mut param_list = Vec::new();
 	let mut param_ty_list = Vec::new();
 	let mut internal_param_ty_list = Vec::new();
-	let mut internal_param_list = Vec::new();
 	for (i, arg) in fn_item.sig.inputs.iter().enumerate() {
 		match arg {
 			syn::FnArg::Typed(pat_type) => {
@@ -143,7 +326,6 @@ fn parse_fn<const IS_MOD_ITEM: bool>(
 					_ => unreachable!(),
 				};
 				param_list.push(param_name.clone());
-				internal_param_list.push(param_name.clone());
 				param_ty_list.push(quote!(#param_name : #ty));
 				internal_param_ty_list.push(quote!(#param_name : #ty));
 			}
@@ -168,7 +350,6 @@ fn parse_fn<const IS_MOD_ITEM: bool>(
 					let ty = rec.ty.to_token_stream();
 					let param_name = format!("p{i}").parse::<TokenStream2>().unwrap();
 					param_list.push(quote! {self});
-					internal_param_list.push(param_name.clone());
 					param_ty_list.push(quote!(self : #ty));
 					internal_param_ty_list.push(quote!(#param_name : #ty));
 				}
@@ -177,16 +358,37 @@ fn parse_fn<const IS_MOD_ITEM: bool>(
 	}
 
 	let lint;
-	let link_name = match &attr_data.link_name {
-		Some((name, _)) => {
+	let link_names = match item_data
+		.link_name
+		.as_ref()
+		.or(attr_data.link_name.as_ref())
+	{
+		Some((names, _)) => {
 			lint = TokenStream2::default();
-			name.clone()
+			names.clone()
 		}
 		None => {
 			lint = quote! {#[allow(non_snake_case)]};
-			fn_name.to_string()
+			vec![attr_data.mapping.apply(&fn_item.sig.ident.to_string())]
 		}
 	};
+	// declarations given to `cpp` must take the parameters of the function.
+	let cpp = if item_data.link_name.is_some() {
+		&item_data.cpp
+	} else {
+		&attr_data.cpp
+	};
+	if let Some((params, span)) = cpp {
+		let inputs = fn_item.sig.inputs.len();
+		if let Err(e) = params.check(inputs, fn_item.sig.variadic.is_some()) {
+			return syn::Error::new(*span, format!("Invalid declaration: {e}."))
+				.into_compile_error();
+		}
+	}
+
+	// the first name identifies the function, the rest are alternatives tried in order.
+	let link_name = &link_names[0];
+	let link_names = quote!(&[#(#link_names),*]);
 
 	// This is mainly useful for applying lifetimes.
 	let generics = &fn_item.sig.generics;
@@ -203,30 +405,425 @@ fn parse_fn<const IS_MOD_ITEM: bool>(
 		Some(token) => token.to_token_stream(),
 	};
 
-	// According to "The Rustonomicon" foreign functions are assumed unsafe,
-	// so functions are implicitly prepended with `unsafe`
-	quote! {
-		#(#fn_attrs)*
-		#lint
-		#[inline]
-		#vis #asyncness unsafe #abi fn #generics #fn_name (#(#param_ty_list),* #variadic) #output {
-			use ::std::sync::atomic::{AtomicPtr, Ordering};
-			static FUNC: AtomicPtr<::std::ffi::c_void> = AtomicPtr::new(
-				initializer as *mut _
+	let pfn_ty = quote!(#abi fn (#(#internal_param_ty_list),*) #output);
+
+	// According to "The Rustonomicon" foreign functions are assumed unsafe, so functions are
+	// implicitly prepended with `unsafe`, unless declared `safe` in an `unsafe extern` block.
+	let unsafety = if safe {
+		TokenStream2::default()
+	} else {
+		quote!(unsafe)
+	};
+
+	let lifetimes = &generics.params;
+	let higher_ranked = if lifetimes.is_empty() {
+		TokenStream2::default()
+	} else {
+		quote!(for<#lifetimes>)
+	};
+
+	// The function and its accessors each declare their thunk. With `testing`, they share one
+	// through a key unique to this expansion, so overrides set through the accessors apply to the
+	// function. The function pattern may be used in `impl` blocks, where its accessors are only
+	// reachable through `Self`, so they can't share it through a path.
+	let (thunk, thunk_decl) = if cfg!(feature = "testing") {
+		let key = format!(
+			"{}#{}",
+			fn_item.sig.ident,
+			EXPANSIONS.fetch_add(1, Ordering::Relaxed)
+		);
+		(
+			quote!(THUNK.get()),
+			quote!(
+				static THUNK: ::dylink::thunk::SharedThunk =
+					::dylink::thunk::SharedThunk::new(concat!(module_path!(), "::", #key));
+			),
+		)
+	} else {
+		(
+			quote!((&THUNK)),
+			quote!(
+				static THUNK: ::dylink::thunk::Thunk = ::dylink::thunk::Thunk::new();
+			),
+		)
+	};
+
+	// The source is bound to a local once, since the guard returned by `enter` borrows it. The
+	// local is hygienic, so it can't collide with parameters. Sources must be `'static`, since
+	// their symbols are cached, while loaders are stateless.
+	let source = syn::Ident::new("source", proc_macro2::Span::mixed_site());
+	let bind_source = match library {
+		Library::Source(expr) => quote_spanned! {expr.span()=>
+			#[allow(clippy::needless_borrow)]
+			let #source = {
+				use ::dylink::thunk::StaticSource as _;
+				(#expr).dylink_source()
+			};
+		},
+		Library::Loader(expr) => quote_spanned! {expr.span()=>
+			let #source = &::dylink::thunk::Loader(#expr);
+		},
+	};
+
+	// An expression resolving the symbol into an `io::Result<*const Symbol>`. The fallback is
+	// coerced to the declared signature first, so mismatches are caught at compile time.
+	let resolve = match &fallback {
+		None => quote!(#thunk.resolve(#source, #link_names)),
+		Some(fallback) => quote!({
+			let fallback: unsafe #abi fn (#(#internal_param_ty_list),* #variadic) #output = #fallback;
+			::std::io::Result::Ok(#thunk.resolve_or(#source, #link_names, fallback as *const ::dylink::Symbol))
+		}),
+	};
+
+	// traced functions report their resolution and calls to `dylink::hooks`.
+	let trace = attr_data.trace.is_some() || cfg!(feature = "trace");
+	let resolve = if trace {
+		let library_name = library.name();
+		quote!(::dylink::thunk::trace_resolve(#thunk, #source, #library_name, #link_name, || #resolve))
+	} else {
+		resolve
+	};
+	let call = if trace {
+		let name = fn_item.sig.ident.to_string();
+		quote!(::dylink::thunk::trace_call(#name, || pfn(#(#param_list),*)))
+	} else {
+		quote!(pfn(#(#param_list),*))
+	};
+
+	// Each function registers itself through a static constructor, so that
+	// `LibLock::preload_declared` can find it. Items in a function body are still emitted,
+	// which keeps this usable from `impl` blocks.
+	let registration = quote! {
+		static DECLARATION: ::dylink::thunk::Declaration = ::dylink::thunk::Declaration::new(
+			#link_name,
+			|| {
+				#bind_source
+				::dylink::SymbolSource::addr(#source)
+			},
+			|| {
+				#bind_source
+				let _guard = ::dylink::SymbolSource::enter(#source);
+				#resolve
+			},
+		);
+		#[used]
+		#[cfg_attr(
+			all(unix, not(any(target_vendor = "apple", target_os = "aix"))),
+			unsafe(link_section = ".init_array")
+		)]
+		#[cfg_attr(target_vendor = "apple", unsafe(link_section = "__DATA,__mod_init_func"))]
+		#[cfg_attr(windows, unsafe(link_section = ".CRT$XCU"))]
+		static REGISTER: extern "C" fn() = {
+			extern "C" fn register() {
+				::dylink::thunk::register(&DECLARATION)
+			}
+			register
+		};
+	};
+
+	// Variadic functions can't be defined on stable Rust, so they're exposed as a function
+	// returning the function pointer instead, which holds the call guard until it is dropped.
+	if let (Some(_), Some(asyncness)) = (&fn_item.sig.variadic, &fn_item.sig.asyncness) {
+		return syn::Error::new(asyncness.span(), "variadic functions cannot be `async`")
+			.into_compile_error();
+	}
+	let variadic_ty =
+		quote!(#higher_ranked #unsafety #abi fn (#(#internal_param_ty_list),* #variadic) #output);
+	let pfn_name = format_ident!("PFN_{}", fn_item.sig.ident);
+
+	// The bodies of the generated items, which differ between dynamic and static builds.
+	struct Bodies {
+		function: TokenStream2,
+		try_function: TokenStream2,
+		is_available: TokenStream2,
+		ptr: TokenStream2,
+	}
+
+	let expand = |bodies: &Bodies| {
+		let Bodies {
+			function,
+			try_function,
+			is_available,
+			ptr,
+		} = bodies;
+		let mut tokens = if fn_item.sig.variadic.is_some() {
+			quote! {
+				#(#fn_attrs)*
+				#lint
+				#[inline]
+				#vis fn #fn_name() -> ::dylink::thunk::Variadic<'static, #variadic_ty> {
+					#function
+				}
+			}
+		} else {
+			quote! {
+				#(#fn_attrs)*
+				#lint
+				#[inline]
+				#vis #asyncness #unsafety #abi fn #generics #fn_name (#(#param_ty_list),* #variadic) #output {
+					#function
+				}
+			}
+		};
+
+		// the companion's `ptr()` already serves as the fallible version of variadic functions.
+		if attr_data.try_fn.is_some() && fn_item.sig.variadic.is_none() {
+			let try_name = format_ident!("try_{}", fn_item.sig.ident);
+			let try_attrs = fn_attrs.iter().filter(|attr| !attr.path().is_ident("doc"));
+			let ret = match &fn_item.sig.output {
+				syn::ReturnType::Default => quote!(()),
+				syn::ReturnType::Type(_, ty) => ty.to_token_stream(),
+			};
+			let doc = format!(
+				"Fallible version of `{}`, which returns an error instead of panicking if the symbol cannot be loaded.",
+				fn_item.sig.ident
 			);
+			tokens.extend(quote! {
+				#(#try_attrs)*
+				#[doc = #doc]
+				#[allow(non_snake_case)]
+				#[inline]
+				#vis #unsafety fn #generics #try_name (#(#param_ty_list),*) -> ::std::result::Result<#ret, ::dylink::Error> {
+					#try_function
+				}
+			});
+		}
 
-			#asyncness unsafe #abi fn initializer #generics (#(#internal_param_ty_list),* #variadic) #output {
-				let symbol = ::dylink::sync::LibLock::symbol(&#library, #link_name)
-					.expect(&format!("Dylink Error: failed to load `{}`", stringify!(#fn_name)));
-				FUNC.store(symbol.cast_mut().cast(), Ordering::Relaxed);
-				let pfn: #abi fn (#(#internal_param_ty_list),*) #output = ::std::mem::transmute(symbol);
-				pfn(#(#internal_param_list),*)
+		let cfg_attrs = fn_item
+			.attrs
+			.iter()
+			.filter(|attr| attr.path().is_ident("cfg"))
+			.collect::<Vec<_>>();
+		let accessor = |suffix: &str| format_ident!("{}_{suffix}", fn_item.sig.ident);
+		let (is_available_name, ptr_name) = (accessor("is_available"), accessor("ptr"));
+		let is_available_doc = format!(
+			"Returns `true` if the symbol of `{fn_name}` can be loaded, without calling it."
+		);
+		let ptr_doc =
+			format!("Returns a pointer to `{fn_name}`, or `None` if its symbol cannot be loaded.");
+		// type aliases can't be declared in `impl` blocks, where the function pattern may be used.
+		if IS_MOD_ITEM {
+			let pfn_doc = format!("Function pointer type of `{fn_name}`.");
+			tokens.extend(quote! {
+				#(#cfg_attrs)*
+				#[doc = #pfn_doc]
+				#[allow(non_camel_case_types, dead_code)]
+				#vis type #pfn_name = #variadic_ty;
+			});
+		}
+		tokens.extend(quote! {
+			#(#cfg_attrs)*
+			#[doc = #is_available_doc]
+			#[allow(non_snake_case, dead_code)]
+			#[inline]
+			#vis fn #is_available_name() -> bool {
+				#is_available
 			}
 
-			let symbol = FUNC.load(Ordering::Relaxed);
-			::std::sync::atomic::compiler_fence(Ordering::Acquire);
-			let pfn : #abi fn (#(#internal_param_ty_list),*) #output = ::std::mem::transmute(symbol);
-			pfn(#(#param_list),*)
+			#(#cfg_attrs)*
+			#[doc = #ptr_doc]
+			#[allow(non_snake_case, dead_code)]
+			#vis fn #ptr_name() -> ::std::option::Option<#variadic_ty> {
+				#ptr
+			}
+		});
+		if cfg!(feature = "testing") {
+			let (set_override, clear_override, override_with) = (
+				accessor("set_override"),
+				accessor("clear_override"),
+				accessor("override_with"),
+			);
+			let set_override_doc =
+				format!("Replaces `{fn_name}` with `pfn` until `{clear_override}` is called.");
+			let clear_override_doc =
+				format!("Restores `{fn_name}` after it was replaced by `{set_override}`.");
+			let override_with_doc =
+				format!("Replaces `{fn_name}` with `pfn` until the returned guard is dropped.");
+			tokens.extend(quote! {
+				#(#cfg_attrs)*
+				#[doc = #set_override_doc]
+				#[allow(non_snake_case, dead_code)]
+				#[inline]
+				#vis fn #set_override(pfn: #variadic_ty) {
+					#thunk_decl
+					#thunk.set_override(pfn as *const ::dylink::Symbol);
+				}
+
+				#(#cfg_attrs)*
+				#[doc = #clear_override_doc]
+				#[allow(non_snake_case, dead_code)]
+				#[inline]
+				#vis fn #clear_override() {
+					#thunk_decl
+					#thunk.set_override(::std::ptr::null());
+				}
+
+				#(#cfg_attrs)*
+				#[doc = #override_with_doc]
+				#[allow(non_snake_case, dead_code)]
+				#[inline]
+				#vis fn #override_with(pfn: #variadic_ty) -> ::dylink::OverrideGuard<'static> {
+					#thunk_decl
+					#thunk.override_with(pfn as *const ::dylink::Symbol)
+				}
+			});
+		}
+		tokens
+	};
+
+	let function = if fn_item.sig.variadic.is_some() {
+		quote! {
+			#thunk_decl
+			#registration
+			#bind_source
+			let guard = ::dylink::SymbolSource::enter(#source);
+			let symbol = #resolve.unwrap_or_else(|err| {
+				panic!("Dylink Error: failed to load `{}`: {}", stringify!(#fn_name), err)
+			});
+			::dylink::thunk::Variadic::new(unsafe { ::std::mem::transmute(symbol) }, guard)
 		}
+	} else {
+		quote! {
+			#thunk_decl
+			#registration
+			#bind_source
+			let _guard = ::dylink::SymbolSource::enter(#source);
+			let symbol = #resolve.unwrap_or_else(|err| {
+				panic!("Dylink Error: failed to load `{}`: {}", stringify!(#fn_name), err)
+			});
+			let pfn: #pfn_ty = unsafe { ::std::mem::transmute(symbol) };
+			#call
+		}
+	};
+	// a fallback is always available, so check for the symbol itself.
+	let is_available = if fallback.is_some() {
+		quote! {
+			#bind_source
+			let _guard = ::dylink::SymbolSource::enter(#source);
+			(#link_names)
+				.iter()
+				.any(|name| ::dylink::SymbolSource::symbol(#source, name).is_ok())
+		}
+	} else {
+		quote! {
+			#thunk_decl
+			#bind_source
+			let _guard = ::dylink::SymbolSource::enter(#source);
+			#resolve.is_ok()
+		}
+	};
+	let mut tokens = expand(&Bodies {
+		function,
+		try_function: quote! {
+			#thunk_decl
+			#bind_source
+			let _guard = ::dylink::SymbolSource::enter(#source);
+			let symbol = #resolve?;
+			let pfn: #pfn_ty = unsafe { ::std::mem::transmute(symbol) };
+			Ok(#call)
+		},
+		is_available,
+		ptr: quote! {
+			#thunk_decl
+			#bind_source
+			let _guard = ::dylink::SymbolSource::enter(#source);
+			let symbol = #resolve.ok()?;
+			Some(unsafe { ::std::mem::transmute::<*const ::dylink::Symbol, #variadic_ty>(symbol) })
+		},
+	});
+
+	// With `static_link`, the function calls a plain `extern` declaration when the predicate
+	// holds, and the items generated above are only used otherwise. The declaration lives in
+	// the body, so it doesn't leak into the surrounding scope and also works in `impl` blocks.
+	if let Some(predicate) = &attr_data.static_link {
+		let link = attr_data
+			.static_lib
+			.as_ref()
+			.map(|name| quote!(#[link(name = #name)]));
+		let safety = if safe {
+			quote!(safe)
+		} else {
+			TokenStream2::default()
+		};
+		let import = format_ident!("__dylink_{}", fn_item.sig.ident);
+		let declaration = quote! {
+			#link
+			unsafe #abi {
+				#[link_name = #link_name]
+				#safety fn #generics #import (#(#internal_param_ty_list),* #variadic) #output;
+			}
+		};
+		let linked_call = if safe {
+			quote!(#import(#(#param_list),*))
+		} else {
+			quote!(unsafe { #import(#(#param_list),*) })
+		};
+		let linked_call = if trace {
+			let name = fn_item.sig.ident.to_string();
+			quote!(::dylink::thunk::trace_call(#name, || #linked_call))
+		} else {
+			linked_call
+		};
+		// overrides still apply, since they're how tests replace the function.
+		let overrides = cfg!(feature = "testing");
+		let linked_pfn = if overrides {
+			quote! {
+				#declaration
+				#thunk_decl
+				let overridden = #thunk.overridden();
+				let pfn: #variadic_ty = if overridden.is_null() {
+					#import
+				} else {
+					unsafe { ::std::mem::transmute(overridden) }
+				};
+			}
+		} else {
+			quote! {
+				#declaration
+				let pfn: #variadic_ty = #import;
+			}
+		};
+		let function = match (fn_item.sig.variadic.is_some(), overrides) {
+			(true, _) => quote! {
+				#linked_pfn
+				::dylink::thunk::Variadic::linked(pfn)
+			},
+			(false, true) => quote! {
+				#declaration
+				#thunk_decl
+				let overridden = #thunk.overridden();
+				if overridden.is_null() {
+					#linked_call
+				} else {
+					let pfn: #pfn_ty = unsafe { ::std::mem::transmute(overridden) };
+					#call
+				}
+			},
+			(false, false) => quote! {
+				#declaration
+				#linked_call
+			},
+		};
+		let static_tokens = expand(&Bodies {
+			try_function: quote!(Ok({ #function })),
+			function,
+			is_available: quote!(true),
+			ptr: quote! {
+				#linked_pfn
+				Some(pfn)
+			},
+		});
+		let (static_items, items) = match (
+			syn::parse2::<syn::File>(static_tokens),
+			syn::parse2::<syn::File>(tokens),
+		) {
+			(Ok(static_file), Ok(file)) => (static_file.items, file.items),
+			(Err(e), _) | (_, Err(e)) => return e.into_compile_error(),
+		};
+		tokens = quote! {
+			#(#[cfg(#predicate)] #static_items)*
+			#(#[cfg(not(#predicate))] #items)*
+		};
 	}
+	tokens
 }
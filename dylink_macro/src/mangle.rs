@@ -0,0 +1,685 @@
+//! A small Itanium C++ ABI name mangler, for `#[dylink(cpp = "...")]`.
+//!
+//! Only free functions and member functions taking builtin types, named types, pointers, and
+//! references are supported. Templates, operators, constructors, and destructors are not.
+//!
+//! Fixed width integer typedefs and `size_t` are mangled as their underlying types on 64-bit
+//! targets. `std::string` and the iostreams use the standard abbreviations, which libraries
+//! built with an inline namespace, like libstdc++'s C++11 ABI or libc++, don't.
+
+use std::fmt::Write;
+
+enum Type {
+	Builtin(&'static str),
+	Named(Vec<String>),
+	Pointer(Box<Type>),
+	LRef(Box<Type>),
+	RRef(Box<Type>),
+	Qualified {
+		konst: bool,
+		volatile: bool,
+		inner: Box<Type>,
+	},
+}
+
+/// The parameters of a mangled declaration, which the Rust signature is checked against.
+pub struct Params {
+	count: usize,
+	this: This,
+	variadic: bool,
+}
+
+/// Whether the Rust signature takes `this` before the declared parameters.
+enum This {
+	No,
+	// qualified names may be namespaced functions, static member functions, or member functions.
+	Maybe,
+	// `const` member functions
+	Yes,
+}
+
+impl Params {
+	/// Checks that a Rust signature with `inputs` parameters can call the declaration.
+	pub fn check(&self, inputs: usize, variadic: bool) -> Result<(), String> {
+		if variadic != self.variadic {
+			return Err(if self.variadic {
+				"the declaration is variadic, but the function isn't".to_string()
+			} else {
+				"the function is variadic, but the declaration isn't".to_string()
+			});
+		}
+		let count = self.count;
+		let expected = match self.this {
+			This::No if inputs != count => format!("{count} parameters"),
+			This::Maybe if inputs != count && inputs != count + 1 => {
+				format!("{count} parameters, or {} with `this`", count + 1)
+			}
+			This::Yes if inputs != count + 1 => format!("{} parameters with `this`", count + 1),
+			_ => return Ok(()),
+		};
+		Err(format!(
+			"the declaration takes {expected}, but the function takes {inputs}"
+		))
+	}
+}
+
+/// Mangles a function declaration, such as `ns::Widget::resize(int, float) const`.
+pub fn mangle(decl: &str) -> Result<(String, Params), String> {
+	let tokens = tokenize(decl)?;
+	let mut parser = Parser { tokens, pos: 0 };
+	let name = parser.name()?;
+	parser.expect("(")?;
+	let mut params = Vec::new();
+	let mut variadic = false;
+	if !parser.eat(")") {
+		loop {
+			if parser.eat("...") {
+				variadic = true;
+				parser.expect(")")?;
+				break;
+			}
+			params.push(parser.ty()?);
+			if parser.eat(")") {
+				break;
+			}
+			parser.expect(",")?;
+		}
+	}
+	let konst = parser.eat("const");
+	if let Some(token) = parser.tokens.get(parser.pos) {
+		return Err(format!("unexpected `{token}`"));
+	}
+	if konst && name.len() < 2 {
+		return Err("only member functions can be `const`".to_string());
+	}
+
+	// `f(void)` takes no parameters
+	if let [Type::Builtin("v")] = params.as_slice() {
+		params.clear();
+	}
+
+	let mut mangler = Mangler::default();
+	let mut out = String::from("_Z");
+	mangler.function_name(&mut out, &name, konst);
+	if params.is_empty() && !variadic {
+		out.push('v');
+	}
+	for param in &params {
+		mangler.ty(&mut out, strip_top_level_cv(param));
+	}
+	if variadic {
+		out.push('z');
+	}
+	let this = match (konst, name.len()) {
+		(true, _) => This::Yes,
+		(false, 1) => This::No,
+		(false, _) => This::Maybe,
+	};
+	let params = Params {
+		count: params.len(),
+		this,
+		variadic,
+	};
+	Ok((out, params))
+}
+
+// top-level qualifiers of parameters aren't part of the signature.
+fn strip_top_level_cv(ty: &Type) -> &Type {
+	match ty {
+		Type::Qualified { inner, .. } => inner,
+		ty => ty,
+	}
+}
+
+fn tokenize(decl: &str) -> Result<Vec<String>, String> {
+	let mut tokens = Vec::new();
+	let mut chars = decl.chars().peekable();
+	while let Some(&c) = chars.peek() {
+		if c.is_whitespace() {
+			chars.next();
+		} else if c.is_ascii_alphanumeric() || c == '_' {
+			let mut ident = String::new();
+			while let Some(&c) = chars
+				.peek()
+				.filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+			{
+				ident.push(c);
+				chars.next();
+			}
+			tokens.push(ident);
+		} else {
+			chars.next();
+			let token = match (c, chars.peek()) {
+				(':', Some(':')) | ('&', Some('&')) => {
+					chars.next();
+					format!("{c}{c}")
+				}
+				('.', Some('.')) => {
+					chars.next();
+					if chars.next() != Some('.') {
+						return Err("expected `...`".to_string());
+					}
+					"...".to_string()
+				}
+				('<' | '>', _) => return Err("templates are unsupported".to_string()),
+				('(' | ')' | ',' | '*' | '&', _) => c.to_string(),
+				_ => return Err(format!("unexpected `{c}`")),
+			};
+			tokens.push(token);
+		}
+	}
+	Ok(tokens)
+}
+
+struct Parser {
+	tokens: Vec<String>,
+	pos: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&str> {
+		self.tokens.get(self.pos).map(String::as_str)
+	}
+
+	fn eat(&mut self, token: &str) -> bool {
+		let found = self.peek() == Some(token);
+		if found {
+			self.pos += 1;
+		}
+		found
+	}
+
+	fn expect(&mut self, token: &str) -> Result<(), String> {
+		if self.eat(token) {
+			Ok(())
+		} else {
+			match self.peek() {
+				Some(found) => Err(format!("expected `{token}`, found `{found}`")),
+				None => Err(format!("expected `{token}`")),
+			}
+		}
+	}
+
+	fn ident(&mut self) -> Result<String, String> {
+		match self.peek() {
+			Some(ident) if ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => {
+				if ident == "operator" {
+					return Err("operators are unsupported".to_string());
+				}
+				let ident = ident.to_string();
+				self.pos += 1;
+				Ok(ident)
+			}
+			Some(found) => Err(format!("expected identifier, found `{found}`")),
+			None => Err("expected identifier".to_string()),
+		}
+	}
+
+	// a possibly qualified name, such as `ns::Widget`.
+	fn name(&mut self) -> Result<Vec<String>, String> {
+		self.eat("::");
+		let mut name = vec![self.ident()?];
+		while self.eat("::") {
+			name.push(self.ident()?);
+		}
+		if let [.., class, function] = name.as_slice() {
+			if class == function {
+				return Err("constructors are unsupported".to_string());
+			}
+		}
+		Ok(name)
+	}
+
+	fn ty(&mut self) -> Result<Type, String> {
+		let (mut konst, mut volatile) = (false, false);
+		let mut words: Vec<String> = Vec::new();
+		let mut named = None;
+		loop {
+			match self.peek() {
+				Some("const") => konst = true,
+				Some("volatile") => volatile = true,
+				Some(
+					"void" | "bool" | "char" | "short" | "int" | "long" | "float" | "double"
+					| "signed" | "unsigned" | "wchar_t" | "char8_t" | "char16_t" | "char32_t"
+					| "__int128",
+				) if named.is_none() => words.push(self.peek().unwrap_or_default().to_string()),
+				Some(token)
+					if named.is_none()
+						&& words.is_empty()
+						&& (token == "::"
+							|| token
+								.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')) =>
+				{
+					named = Some(self.name()?);
+					continue;
+				}
+				_ => break,
+			}
+			self.pos += 1;
+		}
+		let mut ty = match named {
+			Some(name) => match alias(&name)? {
+				Some(code) => Type::Builtin(code),
+				None => Type::Named(name),
+			},
+			None => Type::Builtin(builtin(&words)?),
+		};
+		if konst || volatile {
+			ty = Type::Qualified {
+				konst,
+				volatile,
+				inner: Box::new(ty),
+			};
+		}
+		loop {
+			ty = if self.eat("*") {
+				Type::Pointer(Box::new(ty))
+			} else if self.eat("&") {
+				Type::LRef(Box::new(ty))
+			} else if self.eat("&&") {
+				Type::RRef(Box::new(ty))
+			} else if matches!(self.peek(), Some("const" | "volatile")) {
+				let (mut konst, mut volatile) = (false, false);
+				while let Some(qualifier @ ("const" | "volatile")) = self.peek() {
+					konst |= qualifier == "const";
+					volatile |= qualifier == "volatile";
+					self.pos += 1;
+				}
+				Type::Qualified {
+					konst,
+					volatile,
+					inner: Box::new(ty),
+				}
+			} else {
+				break;
+			};
+		}
+		// parameter names are allowed, but ignored.
+		if self
+			.peek()
+			.is_some_and(|token| token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_'))
+		{
+			self.pos += 1;
+		}
+		Ok(ty)
+	}
+}
+
+fn builtin(words: &[String]) -> Result<&'static str, String> {
+	let count = |word: &str| words.iter().filter(|w| *w == word).count();
+	let unsigned = count("unsigned") > 0;
+	let longs = count("long");
+	let base = words
+		.iter()
+		.map(String::as_str)
+		.find(|word| !matches!(*word, "signed" | "unsigned" | "long" | "short" | "int"));
+	let code = match (base, longs, count("short") > 0, unsigned) {
+		(Some("void"), 0, false, false) => "v",
+		(Some("bool"), 0, false, false) => "b",
+		(Some("char"), 0, false, false) if count("signed") > 0 => "a",
+		(Some("char"), 0, false, false) => "c",
+		(Some("char"), 0, false, true) => "h",
+		(Some("wchar_t"), 0, false, false) => "w",
+		(Some("char8_t"), 0, false, false) => "Du",
+		(Some("char16_t"), 0, false, false) => "Ds",
+		(Some("char32_t"), 0, false, false) => "Di",
+		(Some("float"), 0, false, false) => "f",
+		(Some("double"), 0, false, false) => "d",
+		(Some("double"), 1, false, false) => "e",
+		(Some("__int128"), 0, false, false) => "n",
+		(Some("__int128"), 0, false, true) => "o",
+		(None, 0, true, false) => "s",
+		(None, 0, true, true) => "t",
+		(None, 0, false, false) if !words.is_empty() => "i",
+		(None, 0, false, true) => "j",
+		(None, 1, false, false) => "l",
+		(None, 1, false, true) => "m",
+		(None, 2, false, false) => "x",
+		(None, 2, false, true) => "y",
+		_ if words.is_empty() => return Err("expected type".to_string()),
+		_ => return Err(format!("unsupported type `{}`", words.join(" "))),
+	};
+	Ok(code)
+}
+
+// the encoding of a typedef or standard abbreviation, which aren't substitution candidates.
+fn alias(name: &[String]) -> Result<Option<&'static str>, String> {
+	if let Some(code) = std_abbreviation(name) {
+		return Ok(Some(code));
+	}
+	let typedef = match name {
+		[typedef] => typedef,
+		[std, typedef] if std == "std" => typedef,
+		_ => return Ok(None),
+	};
+	let code = match typedef.as_str() {
+		"int8_t" => "a",
+		"uint8_t" => "h",
+		"int16_t" => "s",
+		"uint16_t" => "t",
+		"int32_t" => "i",
+		"uint32_t" => "j",
+		"size_t" | "uintptr_t" => "m",
+		"ssize_t" | "ptrdiff_t" | "intptr_t" => "l",
+		"int64_t" | "uint64_t" | "intmax_t" | "uintmax_t" => {
+			return Err(format!(
+				"`{typedef}` is `long` on some targets and `long long` on others, use the underlying type instead"
+			))
+		}
+		"allocator" | "basic_string" | "basic_istream" | "basic_ostream" | "basic_iostream"
+			if name.len() == 2 =>
+		{
+			return Err("templates are unsupported".to_string())
+		}
+		_ => return Ok(None),
+	};
+	Ok(Some(code))
+}
+
+// the standard abbreviation of `std::string` and the iostreams.
+fn std_abbreviation(name: &[String]) -> Option<&'static str> {
+	match name {
+		[std, name] if std == "std" => match name.as_str() {
+			"string" => Some("Ss"),
+			"istream" => Some("Si"),
+			"ostream" => Some("So"),
+			"iostream" => Some("Sd"),
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+/// Mangles names and types, keeping track of substitution candidates.
+#[derive(Default)]
+struct Mangler {
+	// unsubstituted encodings of the candidates, in order of appearance.
+	substitutions: Vec<String>,
+}
+
+impl Mangler {
+	fn substitution(&self, key: &str) -> Option<String> {
+		let index = self.substitutions.iter().position(|sub| sub == key)?;
+		Some(match index {
+			0 => "S_".to_string(),
+			n => format!("S{}_", to_base36(n - 1)),
+		})
+	}
+
+	fn add(&mut self, key: String) {
+		if !self.substitutions.contains(&key) {
+			self.substitutions.push(key);
+		}
+	}
+
+	fn function_name(&mut self, out: &mut String, name: &[String], konst: bool) {
+		match name {
+			[function] => write_source_name(out, function),
+			[std, function] if std == "std" => {
+				out.push_str("St");
+				write_source_name(out, function);
+			}
+			[prefix @ .., function] => {
+				out.push('N');
+				if konst {
+					out.push('K');
+				}
+				self.prefix(out, prefix);
+				write_source_name(out, function);
+				out.push('E');
+			}
+			[] => unreachable!("names have at least one component"),
+		}
+	}
+
+	// writes the components of a nested name, starting from the longest prefix that's already a
+	// candidate, and adds the remaining prefixes as candidates.
+	fn prefix(&mut self, out: &mut String, prefix: &[String]) {
+		let keys: Vec<String> = (1..=prefix.len())
+			.map(|len| named_key(&prefix[..len]))
+			.collect();
+		let start = match (0..prefix.len())
+			.rev()
+			.find_map(|i| self.substitution(&keys[i]).map(|sub| (i, sub)))
+		{
+			Some((i, sub)) => {
+				out.push_str(&sub);
+				i + 1
+			}
+			None => match prefix.get(..2).and_then(std_abbreviation) {
+				Some(code) => {
+					out.push_str(code);
+					2
+				}
+				None if prefix[0] == "std" => {
+					out.push_str("St");
+					1
+				}
+				None => 0,
+			},
+		};
+		for (component, key) in prefix.iter().zip(keys).skip(start) {
+			write_source_name(out, component);
+			self.add(key);
+		}
+	}
+
+	// writes a type, returning its unsubstituted encoding.
+	fn ty(&mut self, out: &mut String, ty: &Type) -> String {
+		let key = match ty {
+			Type::Builtin(code) => {
+				out.push_str(code);
+				return code.to_string();
+			}
+			Type::Named(name) => {
+				let key = named_key(name);
+				if let Some(sub) = self.substitution(&key) {
+					out.push_str(&sub);
+					return key;
+				}
+				match name.as_slice() {
+					[component] => write_source_name(out, component),
+					[std, component] if std == "std" => {
+						out.push_str("St");
+						write_source_name(out, component);
+					}
+					[prefix @ .., component] => {
+						out.push('N');
+						self.prefix(out, prefix);
+						write_source_name(out, component);
+						out.push('E');
+					}
+					[] => unreachable!("names have at least one component"),
+				}
+				key
+			}
+			Type::Pointer(inner) | Type::LRef(inner) | Type::RRef(inner) => {
+				let code = match ty {
+					Type::Pointer(_) => 'P',
+					Type::LRef(_) => 'R',
+					_ => 'O',
+				};
+				let mut inner_out = String::new();
+				let inner_key = self.ty_key(inner);
+				let key = format!("{code}{inner_key}");
+				if let Some(sub) = self.substitution(&key) {
+					out.push_str(&sub);
+					return key;
+				}
+				self.ty(&mut inner_out, inner);
+				out.push(code);
+				out.push_str(&inner_out);
+				key
+			}
+			Type::Qualified {
+				konst,
+				volatile,
+				inner,
+			} => {
+				let mut qualifiers = String::new();
+				if *volatile {
+					qualifiers.push('V');
+				}
+				if *konst {
+					qualifiers.push('K');
+				}
+				let key = format!("{qualifiers}{}", self.ty_key(inner));
+				if let Some(sub) = self.substitution(&key) {
+					out.push_str(&sub);
+					return key;
+				}
+				out.push_str(&qualifiers);
+				self.ty(out, inner);
+				key
+			}
+		};
+		self.add(key.clone());
+		key
+	}
+
+	// the unsubstituted encoding of a type, which identifies it in the substitution table.
+	fn ty_key(&self, ty: &Type) -> String {
+		match ty {
+			Type::Builtin(code) => code.to_string(),
+			Type::Named(name) => named_key(name),
+			Type::Pointer(inner) => format!("P{}", self.ty_key(inner)),
+			Type::LRef(inner) => format!("R{}", self.ty_key(inner)),
+			Type::RRef(inner) => format!("O{}", self.ty_key(inner)),
+			Type::Qualified {
+				konst,
+				volatile,
+				inner,
+			} => {
+				let mut key = String::new();
+				if *volatile {
+					key.push('V');
+				}
+				if *konst {
+					key.push('K');
+				}
+				key + &self.ty_key(inner)
+			}
+		}
+	}
+}
+
+// named types are keyed by their prefix encoding, matching the keys of nested name prefixes.
+fn named_key(name: &[String]) -> String {
+	let mut key = String::new();
+	let mut skip = 0;
+	if let Some(code) = name.get(..2).and_then(std_abbreviation) {
+		key.push_str(code);
+		skip = 2;
+	}
+	for (i, component) in name.iter().enumerate().skip(skip) {
+		if i == 0 && component == "std" {
+			key.push_str("St");
+		} else {
+			write_source_name(&mut key, component);
+		}
+	}
+	key
+}
+
+fn write_source_name(out: &mut String, name: &str) {
+	let _ = write!(out, "{}{name}", name.len());
+}
+
+fn to_base36(mut n: usize) -> String {
+	const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+	let mut digits = Vec::new();
+	loop {
+		digits.push(DIGITS[n % 36]);
+		n /= 36;
+		if n == 0 {
+			break;
+		}
+	}
+	digits.reverse();
+	String::from_utf8(digits).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// expected names are taken from g++ with `-D_GLIBCXX_USE_CXX11_ABI=0`.
+	#[track_caller]
+	fn assert_mangles(decl: &str, expected: &str) {
+		assert_eq!(mangle(decl).map(|(name, _)| name).as_deref(), Ok(expected));
+	}
+
+	#[test]
+	fn test_functions() {
+		assert_mangles("f8(void)", "_Z2f8v");
+		assert_mangles("f7(char const*, ...)", "_Z2f7PKcz");
+		assert_mangles("std::foo(const char*, unsigned long long)", "_ZSt3fooPKcy");
+		assert_mangles(
+			"ns::Widget::resize(int, float) const",
+			"_ZNK2ns6Widget6resizeEif",
+		);
+	}
+
+	#[test]
+	fn test_substitutions() {
+		assert_mangles("swap(Foo*, Foo*)", "_Z4swapP3FooS0_");
+		assert_mangles("ns::f(ns::Widget const&)", "_ZN2ns1fERKNS_6WidgetE");
+		assert_mangles(
+			"ns::Widget::g(ns::Widget*, ns::Widget::Inner)",
+			"_ZN2ns6Widget1gEPS0_NS0_5InnerE",
+		);
+	}
+
+	#[test]
+	fn test_typedefs() {
+		assert_mangles("f1(size_t)", "_Z2f1m");
+		assert_mangles(
+			"f2(std::size_t, uint32_t, int8_t, uint8_t, int16_t, uint16_t, int32_t)",
+			"_Z2f2mjahsti",
+		);
+		assert_mangles("f6(ptrdiff_t, ssize_t, intptr_t, uintptr_t)", "_Z2f6lllm");
+		assert!(mangle("f(int64_t)").is_err());
+		assert!(mangle("f(std::uint64_t)").is_err());
+	}
+
+	#[test]
+	fn test_std_abbreviations() {
+		assert_mangles("f3(const std::string&)", "_Z2f3RKSs");
+		assert_mangles("f4(std::string, std::string)", "_Z2f4SsSs");
+		assert_mangles(
+			"f5(std::ostream&, std::istream&, std::iostream&)",
+			"_Z2f5RSoRSiRSd",
+		);
+		assert_mangles("f9(const std::string&, const std::string&)", "_Z2f9RKSsS0_");
+		assert_mangles(
+			"f10(std::string*, ns::Widget*, std::string*, ns::Widget*)",
+			"_Z3f10PSsPN2ns6WidgetES_S2_",
+		);
+		assert_mangles("std::string::size() const", "_ZNKSs4sizeEv");
+		assert!(mangle("f(std::allocator)").is_err());
+	}
+
+	#[test]
+	fn test_unsupported() {
+		assert!(mangle("f(std::vector<int>)").is_err());
+		assert!(mangle("ns::Widget::Widget()").is_err());
+		assert!(mangle("operator+(int, int)").is_err());
+		assert!(mangle("f() const").is_err());
+	}
+
+	#[test]
+	fn test_params() {
+		let params = |decl: &str| mangle(decl).map(|(_, params)| params).unwrap();
+		assert!(params("f(int)").check(1, false).is_ok());
+		assert!(params("f(int)").check(2, false).is_err());
+		assert!(params("f(void)").check(0, false).is_ok());
+		assert!(params("ns::f(int)").check(1, false).is_ok());
+		assert!(params("ns::Widget::f(int)").check(2, false).is_ok());
+		assert!(params("ns::Widget::f(int)").check(3, false).is_err());
+		assert!(params("ns::Widget::f(int) const").check(1, false).is_err());
+		assert!(params("ns::Widget::f(int) const").check(2, false).is_ok());
+		assert!(params("f(int, ...)").check(1, true).is_ok());
+		assert!(params("f(int, ...)").check(1, false).is_err());
+		assert!(params("f(int)").check(1, true).is_err());
+	}
+}
@@ -0,0 +1,181 @@
+//! Hooks for observing functions generated by [`dylink`](crate::dylink).
+//!
+//! Functions declared with `#[dylink(trace)]`, or any function when the `trace` feature is
+//! enabled, report their events to the global [`Subscriber`], which can be installed once with
+//! [`set_subscriber`].
+//!
+//! With the `log` or `tracing` feature, events are forwarded to that crate with the `dylink`
+//! target until a subscriber is installed, through [`LogSubscriber`] or [`TracingSubscriber`].
+//! If both features are enabled, events are forwarded to `tracing`.
+//!
+//! # Examples
+//!
+//! ```
+//! use dylink::hooks::{self, CallEvent, ResolveEvent, Subscriber};
+//!
+//! struct Stderr;
+//!
+//! impl Subscriber for Stderr {
+//!     fn on_resolve(&self, event: &ResolveEvent) {
+//!         eprintln!("resolved `{}` in {:?}", event.symbol, event.elapsed);
+//!     }
+//!
+//!     fn on_call(&self, event: &CallEvent) {
+//!         eprintln!("called `{}` in {:?}", event.name, event.elapsed);
+//!     }
+//! }
+//!
+//! static STDERR: Stderr = Stderr;
+//! assert!(hooks::set_subscriber(&STDERR).is_ok());
+//! ```
+
+use std::io;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::Symbol;
+
+static SUBSCRIBER: OnceLock<&'static dyn Subscriber> = OnceLock::new();
+
+/// A receiver of events from traced functions.
+///
+/// Events are reported on the thread they occur on, so implementors should return quickly.
+pub trait Subscriber: Sync {
+	/// Called after a traced function resolves its symbol. Cached addresses aren't reported.
+	#[inline]
+	fn on_resolve(&self, event: &ResolveEvent) {
+		let _ = event;
+	}
+
+	/// Called after a traced function returns.
+	#[inline]
+	fn on_call(&self, event: &CallEvent) {
+		let _ = event;
+	}
+}
+
+/// The resolution of a symbol, as reported to [`Subscriber::on_resolve`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ResolveEvent<'a> {
+	/// The `library` or `loader` argument of the function, as written.
+	pub library: &'a str,
+	/// The name of the symbol.
+	pub symbol: &'a str,
+	/// The resolved address, or the reason it couldn't be resolved.
+	pub result: &'a io::Result<*const Symbol>,
+	/// The time taken to resolve the symbol, including loading the library.
+	pub elapsed: Duration,
+}
+
+/// A call to a function, as reported to [`Subscriber::on_call`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct CallEvent<'a> {
+	/// The name of the function.
+	pub name: &'a str,
+	/// The time taken by the call, including resolving the symbol.
+	pub elapsed: Duration,
+}
+
+/// Installs the global subscriber.
+///
+/// # Errors
+///
+/// The subscriber can only be installed once. If one is already installed, `subscriber` is
+/// returned.
+pub fn set_subscriber(subscriber: &'static dyn Subscriber) -> Result<(), &'static dyn Subscriber> {
+	SUBSCRIBER.set(subscriber)
+}
+
+/// Returns the global subscriber, if one is installed.
+///
+/// With the `log` or `tracing` feature, the forwarding subscriber is returned if none is installed.
+#[inline]
+pub fn subscriber() -> Option<&'static dyn Subscriber> {
+	SUBSCRIBER.get().copied().or(DEFAULT)
+}
+
+#[cfg(feature = "tracing")]
+const DEFAULT: Option<&'static dyn Subscriber> = Some(&TracingSubscriber);
+#[cfg(all(feature = "log", not(feature = "tracing")))]
+const DEFAULT: Option<&'static dyn Subscriber> = Some(&LogSubscriber);
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+const DEFAULT: Option<&'static dyn Subscriber> = None;
+
+/// A subscriber forwarding events to the [`log`] crate.
+///
+/// Resolutions and calls are logged at the `trace` level, and failed resolutions at the `debug`
+/// level, with the `dylink` target.
+#[cfg(feature = "log")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogSubscriber;
+
+#[cfg(feature = "log")]
+impl Subscriber for LogSubscriber {
+	fn on_resolve(&self, event: &ResolveEvent) {
+		match event.result {
+			Ok(addr) => log::trace!(
+				target: "dylink",
+				"resolved `{}` from `{}` at {:p} in {:?}",
+				event.symbol,
+				event.library,
+				*addr,
+				event.elapsed
+			),
+			Err(err) => log::debug!(
+				target: "dylink",
+				"failed to resolve `{}` from `{}` in {:?}: {}",
+				event.symbol,
+				event.library,
+				event.elapsed,
+				err
+			),
+		}
+	}
+
+	fn on_call(&self, event: &CallEvent) {
+		log::trace!(target: "dylink", "called `{}` in {:?}", event.name, event.elapsed);
+	}
+}
+
+/// A subscriber forwarding events to the [`tracing`] crate.
+///
+/// Resolutions and calls are recorded as `trace` level events, and failed resolutions as `debug`
+/// level events, with the `dylink` target.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingSubscriber;
+
+#[cfg(feature = "tracing")]
+impl Subscriber for TracingSubscriber {
+	fn on_resolve(&self, event: &ResolveEvent) {
+		match event.result {
+			Ok(addr) => tracing::trace!(
+				target: "dylink",
+				library = event.library,
+				symbol = event.symbol,
+				addr = ?addr,
+				elapsed = ?event.elapsed,
+				"resolved symbol"
+			),
+			Err(err) => tracing::debug!(
+				target: "dylink",
+				library = event.library,
+				symbol = event.symbol,
+				elapsed = ?event.elapsed,
+				error = %err,
+				"failed to resolve symbol"
+			),
+		}
+	}
+
+	fn on_call(&self, event: &CallEvent) {
+		tracing::trace!(
+			target: "dylink",
+			name = event.name,
+			elapsed = ?event.elapsed,
+			"called function"
+		);
+	}
+}
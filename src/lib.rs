@@ -16,6 +16,7 @@ use os::unix as imp;
 #[cfg(windows)]
 use os::windows as imp;
 
+pub mod hooks;
 pub mod img;
 pub mod sync;
 
@@ -25,16 +26,25 @@ pub use weak::Weak;
 mod sym;
 pub use sym::Symbol;
 
+mod source;
+pub use source::{MockFn, MockLibrary, SymbolSource};
+
+#[doc(hidden)]
+pub mod thunk;
+#[cfg(feature = "testing")]
+pub use thunk::OverrideGuard;
+
 use std::{io, path};
 
 pub use dylink_macro::dylink;
 
+/// The error type of fallible operations, such as the `try_` functions generated by [`dylink`].
+pub type Error = io::Error;
+
 #[doc = include_str!("../README.md")]
 #[cfg(all(doctest, windows))]
 struct ReadmeDoctests;
 
-
-
 /// An object providing access to an open dynamic library.
 ///
 /// Errors detected on closing are ignored by the implementation of `Drop`.
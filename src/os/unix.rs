@@ -47,6 +47,20 @@ fn dlopen_fname(fname: &ffi::CStr) -> *const ffi::c_char {
 	}
 }
 
+/// Returns the `errno` of the calling thread, or 0 where it can't be accessed.
+#[inline]
+pub(crate) fn last_error() -> ffi::c_int {
+	unsafe { c::errno_location().as_ref() }.map_or(0, |errno| *errno)
+}
+
+/// Sets the `errno` of the calling thread, unless it can't be accessed.
+#[inline]
+pub(crate) fn set_last_error(code: ffi::c_int) {
+	if let Some(errno) = unsafe { c::errno_location().as_mut() } {
+		*errno = code;
+	}
+}
+
 #[derive(Debug)]
 #[repr(transparent)]
 pub(crate) struct InnerLibrary(ptr::NonNull<ffi::c_void>);
@@ -60,7 +74,7 @@ impl InnerLibrary {
 			Ok(Self(ret))
 		} else {
 			let err = c_dlerror().unwrap();
-			Err(io::Error::new(io::ErrorKind::Other, err.to_string_lossy()))
+			Err(io::Error::other(err.to_string_lossy()))
 		}
 	}
 	pub unsafe fn this() -> io::Result<Self> {
@@ -70,7 +84,7 @@ impl InnerLibrary {
 			Ok(Self(ret))
 		} else {
 			let err = c_dlerror().unwrap();
-			Err(io::Error::new(io::ErrorKind::Other, err.to_string_lossy()))
+			Err(io::Error::other(err.to_string_lossy()))
 		}
 	}
 
@@ -79,7 +93,7 @@ impl InnerLibrary {
 		c::dlsym(self.0.as_ptr(), name.as_ptr()).cast()
 	}
 
-	pub unsafe fn symbol<'a>(&self, name: &str) -> io::Result<*const Symbol> {
+	pub unsafe fn symbol(&self, name: &str) -> io::Result<*const Symbol> {
 		let _lock = dylib_guard();
 		let c_str = ffi::CString::new(name).unwrap();
 
@@ -87,7 +101,7 @@ impl InnerLibrary {
 		let handle = self.raw_symbol(&c_str).cast_mut();
 
 		if let Some(err) = c_dlerror() {
-			Err(io::Error::new(io::ErrorKind::Other, err.to_string_lossy()))
+			Err(io::Error::other(err.to_string_lossy()))
 		} else {
 			Ok(handle)
 		}
@@ -232,10 +246,7 @@ impl SymExt for Symbol {
 				})
 			} else {
 				// dlerror isn't available for dlinfo, so I can only provide a general error message here
-				Err(io::Error::new(
-					io::ErrorKind::Other,
-					"Failed to retrieve symbol information",
-				))
+				Err(io::Error::other("Failed to retrieve symbol information"))
 			}
 		}
 	}
@@ -335,10 +346,7 @@ pub(crate) unsafe fn hdr_size(hdr: *const img::Image) -> io::Result<usize> {
 				)),
 			}
 		}
-		_ => Err(io::Error::new(
-			io::ErrorKind::Other,
-			"unknown header detected",
-		)),
+		_ => Err(io::Error::other("unknown header detected")),
 	}
 }
 
@@ -154,6 +154,76 @@ extern "C" {
 	) -> ffi::c_int;
 }
 
+// Targets exposing the address of `errno` through a function. Elsewhere, such as on DragonFly,
+// where `errno` is a thread-local variable, or on VxWorks, it is left alone.
+#[cfg(any(
+	target_os = "linux",
+	target_os = "emscripten",
+	target_os = "fuchsia",
+	target_os = "hurd",
+	target_os = "redox",
+	target_os = "android",
+	target_os = "netbsd",
+	target_os = "openbsd",
+	target_os = "solaris",
+	target_os = "illumos",
+	target_os = "freebsd",
+	target_vendor = "apple",
+	target_os = "haiku",
+	target_os = "aix",
+	target_os = "nto"
+))]
+extern "C" {
+	#[cfg_attr(
+		any(
+			target_os = "linux",
+			target_os = "emscripten",
+			target_os = "fuchsia",
+			target_os = "hurd",
+			target_os = "redox"
+		),
+		link_name = "__errno_location"
+	)]
+	#[cfg_attr(
+		any(target_os = "android", target_os = "netbsd", target_os = "openbsd"),
+		link_name = "__errno"
+	)]
+	#[cfg_attr(
+		any(target_os = "solaris", target_os = "illumos"),
+		link_name = "___errno"
+	)]
+	#[cfg_attr(
+		any(target_os = "freebsd", target_vendor = "apple"),
+		link_name = "__error"
+	)]
+	#[cfg_attr(target_os = "haiku", link_name = "_errnop")]
+	#[cfg_attr(target_os = "aix", link_name = "_Errno")]
+	#[cfg_attr(target_os = "nto", link_name = "__get_errno_ptr")]
+	pub fn errno_location() -> *mut ffi::c_int;
+}
+
+#[cfg(not(any(
+	target_os = "linux",
+	target_os = "emscripten",
+	target_os = "fuchsia",
+	target_os = "hurd",
+	target_os = "redox",
+	target_os = "android",
+	target_os = "netbsd",
+	target_os = "openbsd",
+	target_os = "solaris",
+	target_os = "illumos",
+	target_os = "freebsd",
+	target_vendor = "apple",
+	target_os = "haiku",
+	target_os = "aix",
+	target_os = "nto"
+)))]
+#[inline]
+pub unsafe fn errno_location() -> *mut ffi::c_int {
+	std::ptr::null_mut()
+}
+
 #[cfg(target_os = "linux")]
 pub type DlIteratePhdrCallback = unsafe extern "C" fn(
 	info: *mut dl_phdr_info,
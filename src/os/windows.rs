@@ -14,6 +14,18 @@ fn to_wide(path: &ffi::OsStr) -> Vec<u16> {
 	path.encode_wide().chain(std::iter::once(0u16)).collect()
 }
 
+/// Returns the last-error code of the calling thread.
+#[inline]
+pub(crate) fn last_error() -> u32 {
+	unsafe { c::GetLastError() }
+}
+
+/// Sets the last-error code of the calling thread.
+#[inline]
+pub(crate) fn set_last_error(code: u32) {
+	unsafe { c::SetLastError(code) }
+}
+
 #[derive(Debug)]
 #[repr(transparent)]
 pub(crate) struct InnerLibrary(std::ptr::NonNull<ffi::c_void>);
@@ -40,7 +52,7 @@ impl InnerLibrary {
 		c::GetProcAddress(self.0.as_ptr(), name.as_ptr()).cast()
 	}
 
-	pub unsafe fn symbol<'a>(&self, name: &str) -> io::Result<*const Symbol> {
+	pub unsafe fn symbol(&self, name: &str) -> io::Result<*const Symbol> {
 		let c_str = ffi::CString::new(name).unwrap();
 		let addr = self.raw_symbol(&c_str);
 		if addr.is_null() {
@@ -211,7 +223,7 @@ pub(crate) unsafe fn hdr_size(hdr: *const img::Image) -> io::Result<usize> {
 
 pub(crate) unsafe fn hdr_path(hdr: *const img::Image) -> io::Result<PathBuf> {
 	let Some(nonnull_hdr) = ptr::NonNull::new(hdr as *mut _) else {
-		return Err(io::Error::new(io::ErrorKind::Other, "invalid header"));
+		return Err(io::Error::other("invalid header"));
 	};
 	let lib = mem::ManuallyDrop::new(InnerLibrary(nonnull_hdr));
 	lib.path()
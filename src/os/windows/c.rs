@@ -150,6 +150,8 @@ extern "system" {
 	pub fn FreeLibrary(hlibmodule: *mut ffi::c_void) -> ffi::c_int;
 	pub fn GetModuleFileNameW(hmodule: HMODULE, lpfilename: PWSTR, nsize: DWORD) -> DWORD;
 	pub fn GetCurrentProcess() -> HANDLE;
+	pub fn GetLastError() -> DWORD;
+	pub fn SetLastError(dwerrcode: DWORD);
 	#[link_name = "K32EnumProcessModulesEx"]
 	pub fn EnumProcessModulesEx(
 		hprocess: HANDLE,
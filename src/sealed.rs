@@ -1,5 +1,6 @@
 pub trait Sealed {}
 
+#[allow(dead_code)]
 #[repr(C)]
 pub struct Opaque {
 	_data: [u8; 0],
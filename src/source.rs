@@ -0,0 +1,374 @@
+use std::any::{Any, TypeId};
+use std::io;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::sync::{CallGuard, LibLock};
+use crate::{Library, Symbol};
+
+/// A source of symbol addresses.
+///
+/// Functions generated by [`dylink`](crate::dylink) resolve their symbols through this trait,
+/// so any implementor can be used as the `library` argument. A [`LibLock`] can also be backed
+/// by one through [`LibLock::with_source`].
+pub trait SymbolSource: Sync {
+	/// Retrieves the address of a symbol.
+	///
+	/// # Errors
+	///
+	/// May error if the symbol is not found.
+	fn symbol(&self, name: &str) -> io::Result<*const Symbol>;
+
+	/// Marks the start of a call through this source, which ends when the guard is dropped.
+	#[doc(hidden)]
+	#[inline]
+	fn enter(&self) -> CallGuard<'_> {
+		CallGuard::none()
+	}
+
+	/// Returns the number of times the symbols of this source have been invalidated.
+	#[doc(hidden)]
+	#[inline]
+	fn generation(&self) -> usize {
+		0
+	}
+
+	/// Returns the address of the source, looking through references, to tell sources apart.
+	#[doc(hidden)]
+	#[inline]
+	fn addr(&self) -> *const () {
+		(self as *const Self).cast()
+	}
+}
+
+impl SymbolSource for Library {
+	#[inline]
+	fn symbol(&self, name: &str) -> io::Result<*const Symbol> {
+		Library::symbol(self, name)
+	}
+}
+
+impl SymbolSource for LibLock<'_> {
+	#[inline]
+	fn symbol(&self, name: &str) -> io::Result<*const Symbol> {
+		LibLock::symbol(self, name)
+	}
+	#[inline]
+	fn enter(&self) -> CallGuard<'_> {
+		LibLock::enter(self)
+	}
+	#[inline]
+	fn generation(&self) -> usize {
+		LibLock::generation(self)
+	}
+}
+
+impl<T: SymbolSource + ?Sized> SymbolSource for &T {
+	#[inline]
+	fn symbol(&self, name: &str) -> io::Result<*const Symbol> {
+		T::symbol(self, name)
+	}
+	#[inline]
+	fn enter(&self) -> CallGuard<'_> {
+		T::enter(self)
+	}
+	#[inline]
+	fn generation(&self) -> usize {
+		T::generation(self)
+	}
+	#[inline]
+	fn addr(&self) -> *const () {
+		T::addr(self)
+	}
+}
+
+/// An in-process symbol source for testing code that uses [`dylink`](crate::dylink).
+///
+/// Symbols are mapped to addresses of functions defined in Rust, which stand in for the real
+/// library, or to closures through [`insert_fn`](MockLibrary::insert_fn). Functions bound to a
+/// `MockLibrary` pick up changes to its symbols on their next call.
+///
+/// # Examples
+///
+/// ```rust
+/// use dylink::*;
+/// use std::ffi::{c_char, c_int};
+///
+/// static LIBC: MockLibrary = MockLibrary::new();
+///
+/// #[dylink(library = LIBC)]
+/// extern "C-unwind" {
+///     fn atoi(s: *const c_char) -> c_int;
+/// }
+///
+/// extern "C-unwind" fn fake_atoi(_: *const c_char) -> c_int {
+///     42
+/// }
+///
+/// LIBC.insert("atoi", fake_atoi as *const Symbol);
+/// assert_eq!(unsafe { atoi(c"5".as_ptr()) }, 42);
+/// ```
+#[derive(Debug, Default)]
+pub struct MockLibrary {
+	symbols: RwLock<Vec<(String, *const Symbol)>>,
+	// bumped whenever a symbol changes, so cached addresses are resolved again.
+	generation: AtomicUsize,
+	// identifies the library in `CLOSURES`, assigned on the first call to `insert_fn`.
+	id: AtomicUsize,
+}
+unsafe impl Send for MockLibrary {}
+unsafe impl Sync for MockLibrary {}
+
+impl MockLibrary {
+	/// Constructs a new `MockLibrary` without any symbols.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			symbols: RwLock::new(Vec::new()),
+			generation: AtomicUsize::new(0),
+			id: AtomicUsize::new(0),
+		}
+	}
+
+	/// Maps `name` to `addr`, returning the address it was previously mapped to.
+	pub fn insert(&self, name: &str, addr: *const Symbol) -> Option<*const Symbol> {
+		let old = self.set(name, addr);
+		self.release(name, None);
+		old
+	}
+
+	fn set(&self, name: &str, addr: *const Symbol) -> Option<*const Symbol> {
+		let mut symbols = self.symbols.write().unwrap_or_else(PoisonError::into_inner);
+		self.generation.fetch_add(1, Ordering::AcqRel);
+		if let Some((_, old)) = symbols.iter_mut().find(|(elem, _)| elem == name) {
+			Some(std::mem::replace(old, addr))
+		} else {
+			symbols.push((name.to_owned(), addr));
+			None
+		}
+	}
+
+	/// Maps `name` to a trampoline calling `f`, returning the address it was previously mapped to.
+	///
+	/// The trampoline uses the `C-unwind` ABI, so `f` can stand in for functions declared with
+	/// either the `C` or `C-unwind` ABI, and panics in `f` propagate through the latter. Every
+	/// name keeps its own closure, so the same closure expression can be mapped to several names
+	/// with different captured state.
+	///
+	/// # Panics
+	///
+	/// Panics if closures of the same type are already mapped to 32 other names, across all
+	/// libraries.
+	///
+	/// # Examples
+	///
+	/// ```rust
+	/// use dylink::*;
+	/// use std::sync::atomic::{AtomicU32, Ordering};
+	///
+	/// static LIB: MockLibrary = MockLibrary::new();
+	///
+	/// #[dylink(library = LIB)]
+	/// extern "C-unwind" {
+	///     fn next_id() -> u32;
+	/// }
+	///
+	/// let counter = AtomicU32::new(0);
+	/// LIB.insert_fn("next_id", move || counter.fetch_add(1, Ordering::Relaxed));
+	/// assert_eq!(unsafe { next_id() }, 0);
+	/// assert_eq!(unsafe { next_id() }, 1);
+	/// ```
+	pub fn insert_fn<Args, F: MockFn<Args>>(&self, name: &str, f: F) -> Option<*const Symbol> {
+		let (ty, id) = (TypeId::of::<F>(), self.id());
+		let mut closures = CLOSURES.write().unwrap_or_else(PoisonError::into_inner);
+		let slot = match closures
+			.iter_mut()
+			.find(|closure| closure.ty == ty && closure.library == id && closure.name == name)
+		{
+			Some(closure) => {
+				closure.f = Arc::new(f);
+				closure.slot
+			}
+			None => {
+				let slot = (0..SLOTS)
+					.find(|&slot| {
+						!closures
+							.iter()
+							.any(|closure| closure.ty == ty && closure.slot == slot)
+					})
+					.unwrap_or_else(|| {
+						panic!("closures of the same type are mapped to more than {SLOTS} names")
+					});
+				closures.push(Closure {
+					ty,
+					slot,
+					library: id,
+					name: name.to_owned(),
+					f: Arc::new(f),
+				});
+				slot
+			}
+		};
+		drop(closures);
+		let old = self.set(name, F::trampoline(slot));
+		self.release(name, Some((ty, slot)));
+		old
+	}
+
+	/// Removes the mapping for `name`, returning the address it was mapped to.
+	pub fn remove(&self, name: &str) -> Option<*const Symbol> {
+		let mut symbols = self.symbols.write().unwrap_or_else(PoisonError::into_inner);
+		let index = symbols.iter().position(|(elem, _)| elem == name)?;
+		self.generation.fetch_add(1, Ordering::AcqRel);
+		let old = symbols.swap_remove(index).1;
+		drop(symbols);
+		self.release(name, None);
+		Some(old)
+	}
+
+	fn id(&self) -> usize {
+		static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+		match self.id.load(Ordering::Acquire) {
+			0 => {
+				let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+				match self
+					.id
+					.compare_exchange(0, id, Ordering::AcqRel, Ordering::Acquire)
+				{
+					Ok(_) => id,
+					Err(id) => id,
+				}
+			}
+			id => id,
+		}
+	}
+
+	// frees the slots of the closures mapped to `name`, except for `keep`.
+	fn release(&self, name: &str, keep: Option<(TypeId, usize)>) {
+		let id = self.id.load(Ordering::Acquire);
+		if id == 0 {
+			return;
+		}
+		let mut closures = CLOSURES.write().unwrap_or_else(PoisonError::into_inner);
+		closures.retain(|closure| {
+			closure.library != id
+				|| closure.name != name
+				|| keep == Some((closure.ty, closure.slot))
+		});
+	}
+}
+
+impl Drop for MockLibrary {
+	fn drop(&mut self) {
+		let id = *self.id.get_mut();
+		if id != 0 {
+			let mut closures = CLOSURES.write().unwrap_or_else(PoisonError::into_inner);
+			closures.retain(|closure| closure.library != id);
+		}
+	}
+}
+
+impl SymbolSource for MockLibrary {
+	fn symbol(&self, name: &str) -> io::Result<*const Symbol> {
+		let symbols = self.symbols.read().unwrap_or_else(PoisonError::into_inner);
+		symbols
+			.iter()
+			.find(|(elem, _)| elem == name)
+			.map(|(_, addr)| *addr)
+			.ok_or_else(|| {
+				io::Error::new(
+					io::ErrorKind::NotFound,
+					format!("symbol `{name}` is not mocked"),
+				)
+			})
+	}
+
+	#[inline]
+	fn generation(&self) -> usize {
+		self.generation.load(Ordering::Acquire)
+	}
+}
+
+// closures inserted through `MockLibrary::insert_fn`, each occupying a trampoline of its type.
+static CLOSURES: RwLock<Vec<Closure>> = RwLock::new(Vec::new());
+
+// the number of trampolines per closure type.
+const SLOTS: usize = 32;
+
+struct Closure {
+	ty: TypeId,
+	slot: usize,
+	// the id of the library and the name the closure is mapped to.
+	library: usize,
+	name: String,
+	f: Arc<dyn Any + Send + Sync>,
+}
+
+/// A closure that can stand in for a foreign function through [`MockLibrary::insert_fn`].
+///
+/// Implemented for closures taking up to 8 arguments.
+pub trait MockFn<Args>: Send + Sync + 'static {
+	/// Returns the address of the trampoline calling the closure of this type in `slot`.
+	#[doc(hidden)]
+	fn trampoline(slot: usize) -> *const Symbol;
+}
+
+// the functions that call closures of type `F` with arguments `Args`.
+struct Trampoline<F, Args>(PhantomData<(F, Args)>);
+
+impl<F: Any + Send + Sync, Args> Trampoline<F, Args> {
+	// the closure of type `F` in `slot`.
+	fn closure(slot: usize) -> Arc<F> {
+		let closures = CLOSURES.read().unwrap_or_else(PoisonError::into_inner);
+		let closure = closures
+			.iter()
+			.find(|closure| closure.ty == TypeId::of::<F>() && closure.slot == slot)
+			.expect("trampolines are only handed out after inserting their closure");
+		Arc::clone(&closure.f).downcast().unwrap()
+	}
+}
+
+// the trampolines of every slot, indexed by slot.
+macro_rules! trampolines {
+	($ty:ty) => {
+		trampolines!($ty; 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31)
+	};
+	($ty:ty; $($slot:literal)*) => {
+		[$(<$ty>::call::<$slot> as *const Symbol),*]
+	};
+}
+
+macro_rules! impl_mock_fn {
+	($($arg:ident),*) => {
+		impl<F, R, $($arg),*> Trampoline<F, ($($arg,)*)>
+		where
+			F: Fn($($arg),*) -> R + Send + Sync + 'static,
+		{
+			#[allow(non_snake_case)]
+			extern "C-unwind" fn call<const SLOT: usize>($($arg: $arg),*) -> R {
+				Self::closure(SLOT)($($arg),*)
+			}
+		}
+
+		impl<F, R, $($arg),*> MockFn<($($arg,)*)> for F
+		where
+			F: Fn($($arg),*) -> R + Send + Sync + 'static,
+		{
+			#[inline]
+			fn trampoline(slot: usize) -> *const Symbol {
+				trampolines!(Trampoline<F, ($($arg,)*)>)[slot]
+			}
+		}
+	};
+}
+
+impl_mock_fn!();
+impl_mock_fn!(A);
+impl_mock_fn!(A, B);
+impl_mock_fn!(A, B, C);
+impl_mock_fn!(A, B, C, D);
+impl_mock_fn!(A, B, C, D, E);
+impl_mock_fn!(A, B, C, D, E, G);
+impl_mock_fn!(A, B, C, D, E, G, H);
+impl_mock_fn!(A, B, C, D, E, G, H, I);
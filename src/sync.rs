@@ -1,6 +1,41 @@
-use std::{io, sync};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{
+	Condvar, Mutex, MutexGuard, OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+	TryLockError,
+};
+use std::{env, error, fmt, io, path, thread};
 
-use crate::{Library, Symbol};
+use crate::thunk::LastErrorGuard;
+use crate::{Library, Symbol, SymbolSource};
+
+mod cache;
+
+/// The state of a [`LibLock`], as returned by [`LibLock::state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum LoadState {
+	/// The library hasn't been loaded yet.
+	Uninit,
+	/// The library is currently being loaded.
+	Loading,
+	/// The library is loaded.
+	Loaded,
+	/// The last attempt to load the library failed.
+	Failed,
+}
+
+impl LoadState {
+	#[inline]
+	fn from_u8(value: u8) -> Self {
+		match value {
+			0 => Self::Uninit,
+			1 => Self::Loading,
+			2 => Self::Loaded,
+			_ => Self::Failed,
+		}
+	}
+}
 
 /// An object providing access to a lazily loaded LibLock on the filesystem.
 ///
@@ -8,8 +43,36 @@ use crate::{Library, Symbol};
 #[derive(Debug)]
 pub struct LibLock<'a> {
 	libs: &'a [&'a str],
+	// replaces `libs` when present
+	loader: Option<fn() -> io::Result<Library>>,
+	on_load: Option<fn(&Library) -> io::Result<()>>,
+	// replaces the library entirely when present
+	source: Option<Source<'a>>,
+	// environment variable that replaces `libs` when set
+	env: Option<&'a str>,
+	env_paths: OnceLock<Vec<path::PathBuf>>,
+	overridden: AtomicBool,
+	state: AtomicU8,
+	// index of the path the library was loaded from
+	candidate: AtomicUsize,
+	reloadable: bool,
+	// number of calls currently being made through the library
+	active: AtomicUsize,
+	reloading: AtomicBool,
+	// signaled when `active` drains to zero during a reload, and when a reload finishes.
+	gate: Mutex<()>,
+	signal: Condvar,
+	generation: AtomicUsize,
+	cache: cache::SymbolCache,
 	// LibLock handle
-	hlib: sync::OnceLock<Library>,
+	hlib: RwLock<Option<Library>>,
+}
+
+thread_local! {
+	// addresses of the reloadable locks this thread is currently calling through.
+	static ENTERED: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+	// addresses of the locks this thread is currently loading.
+	static LOADING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
 }
 
 impl<'a> LibLock<'a> {
@@ -33,13 +96,264 @@ impl<'a> LibLock<'a> {
 	pub const fn new(libs: &'a [&'a str]) -> Self {
 		Self {
 			libs,
-			hlib: sync::OnceLock::new(),
+			loader: None,
+			on_load: None,
+			source: None,
+			env: None,
+			env_paths: OnceLock::new(),
+			overridden: AtomicBool::new(false),
+			state: AtomicU8::new(LoadState::Uninit as u8),
+			candidate: AtomicUsize::new(usize::MAX),
+			reloadable: false,
+			active: AtomicUsize::new(0),
+			reloading: AtomicBool::new(false),
+			gate: Mutex::new(()),
+			signal: Condvar::new(),
+			generation: AtomicUsize::new(0),
+			cache: cache::SymbolCache::new(),
+			hlib: RwLock::new(None),
+		}
+	}
+
+	/// Constructs a new `LibLock` that opens its library through `loader`.
+	///
+	/// `loader` is called in place of opening a list of paths, so any logic can be used to find
+	/// and open the library. It is called at most once per initialization, with the same lazy and
+	/// thread-safe semantics as [`LibLock::new`].
+	///
+	/// # Examples
+	///
+	/// ```rust
+	/// # use dylink::*;
+	/// static VULKAN: sync::LibLock = sync::LibLock::with_loader(|| {
+	///     let sdk = std::env::var("VULKAN_SDK").map_err(std::io::Error::other)?;
+	///     Library::open(std::path::Path::new(&sdk).join("lib/libvulkan.so.1"))
+	/// });
+	/// ```
+	#[inline]
+	pub const fn with_loader(loader: fn() -> io::Result<Library>) -> Self {
+		let mut this = Self::new(&[]);
+		this.loader = Some(loader);
+		this
+	}
+
+	/// Constructs a new `LibLock` that resolves symbols through `source` rather than a library.
+	///
+	/// This is mainly useful for substituting a [`MockLibrary`](crate::MockLibrary) in tests.
+	/// Since no library is loaded, the `LibLock` is always [ready](LibLock::is_ready), and
+	/// symbols aren't cached.
+	///
+	/// # Examples
+	///
+	/// ```rust
+	/// # use dylink::*;
+	/// static MOCK: MockLibrary = MockLibrary::new();
+	/// static FOO: sync::LibLock = if cfg!(test) {
+	///     sync::LibLock::with_source(&MOCK)
+	/// } else {
+	///     sync::LibLock::new(&["libfoo.so"])
+	/// };
+	/// ```
+	#[inline]
+	pub const fn with_source(source: &'a dyn SymbolSource) -> Self {
+		let mut this = Self::new(&[]);
+		this.source = Some(Source(source));
+		this
+	}
+
+	/// Declares a hook that validates each library right after it is opened.
+	///
+	/// If `on_load` returns an error, the library is closed and the next path is tried instead.
+	/// The error is returned when no path is accepted.
+	///
+	/// # Examples
+	///
+	/// Skips any `libfoo` that is missing `foo_v2_entry`:
+	///
+	/// ```rust
+	/// # use dylink::*;
+	/// static FOO: sync::LibLock = sync::LibLock::new(&["libfoo.so.1", "libfoo.so.2"])
+	///     .on_load(|lib: &Library| lib.symbol("foo_v2_entry").map(drop));
+	/// ```
+	#[inline]
+	pub const fn on_load(mut self, on_load: fn(&Library) -> io::Result<()>) -> Self {
+		self.on_load = Some(on_load);
+		self
+	}
+
+	/// Declares an environment variable that overrides the paths given to [`LibLock::new`].
+	///
+	/// When the variable is set to a non-empty value at the time the library is first loaded, it is
+	/// parsed as a list of paths using the platform's `PATH` separator (`:` on unix, `;` on windows),
+	/// and only those paths are tried, in order. Otherwise the regular paths are used. The variable
+	/// is read once per initialization, so a [reload](LibLock::reload) uses the same paths, while
+	/// loading again after [`take`](LibLock::take) reads it again.
+	///
+	/// Use [`is_overridden`](LibLock::is_overridden) to find out which of the two was used.
+	///
+	/// # Examples
+	///
+	/// ```rust
+	/// # use dylink::*;
+	/// static CUDA: sync::LibLock =
+	///     sync::LibLock::new(&["libcuda.so.1", "libcuda.so"]).env_override("MYAPP_LIBCUDA");
+	/// ```
+	#[inline]
+	pub const fn env_override(mut self, var: &'a str) -> Self {
+		self.env = Some(var);
+		self
+	}
+
+	/// Returns `true` if the library was loaded from the paths in the [override variable].
+	///
+	/// Returns `false` if the library is loaded from the regular paths, or hasn't been loaded yet.
+	///
+	/// [override variable]: LibLock::env_override
+	#[inline]
+	pub fn is_overridden(&self) -> bool {
+		self.overridden.load(Ordering::Acquire)
+	}
+
+	/// Allows the library to be swapped out at run-time through [`reload`](LibLock::reload).
+	///
+	/// Functions generated by [`dylink`](crate::dylink) keep track of the calls made through a
+	/// reloadable `LibLock`, which adds a small cost to every call.
+	///
+	/// # Examples
+	///
+	/// ```rust
+	/// # use dylink::*;
+	/// static GAMEPLAY: sync::LibLock = sync::LibLock::new(&["libgameplay.so"]).reloadable();
+	/// ```
+	#[inline]
+	pub const fn reloadable(mut self) -> Self {
+		self.reloadable = true;
+		self
+	}
+
+	/// Closes the library and opens it again.
+	///
+	/// Blocks until every call made through [`dylink`](crate::dylink) functions bound to this
+	/// `LibLock` has returned, and holds back new calls until the library is reopened. Only then
+	/// is the old library closed, and any addresses cached by `dylink` functions are invalidated.
+	///
+	/// Addresses previously returned by [`symbol`](LibLock::symbol) must not be used after this
+	/// function is called. The library is only unloaded by the system if there are no other
+	/// handles to it.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the `LibLock` wasn't constructed with [`reloadable`](LibLock::reloadable),
+	/// if called from within a call through this `LibLock`, or if the library failed to be reopened.
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// # use dylink::*;
+	/// static GAMEPLAY: sync::LibLock = sync::LibLock::new(&["libgameplay.so"]).reloadable();
+	///
+	/// GAMEPLAY.reload().unwrap();
+	/// ```
+	pub fn reload(&self) -> io::Result<()> {
+		if !self.reloadable {
+			return Err(io::Error::new(
+				io::ErrorKind::Unsupported,
+				"LibLock is not reloadable",
+			));
+		}
+		self.check_reentrancy()?;
+		let addr = self as *const Self as usize;
+		if ENTERED.with_borrow(|entered| entered.contains(&addr)) {
+			return Err(io::Error::new(
+				io::ErrorKind::WouldBlock,
+				"LibLock cannot be reloaded from within a call through it",
+			));
+		}
+		let mut gate = self.gate();
+		// only one reload runs at a time.
+		while self.reloading.load(Ordering::SeqCst) {
+			gate = self.wait_signal(gate);
+		}
+		self.reloading.store(true, Ordering::SeqCst);
+		// wait for in-flight calls to drain
+		while self.active.load(Ordering::SeqCst) != 0 {
+			gate = self.wait_signal(gate);
+		}
+		drop(gate);
+		let mut hlib = self.write();
+		// the old library must be closed first, otherwise it would just be handed back.
+		drop(hlib.take());
+		// every reader holds a call guard, so there can't be any left.
+		unsafe { self.cache.clear() };
+		self.generation.fetch_add(1, Ordering::AcqRel);
+		let result = self.open(&mut hlib);
+		drop(hlib);
+		let _gate = self.gate();
+		self.reloading.store(false, Ordering::SeqCst);
+		self.signal.notify_all();
+		result
+	}
+
+	/// Marks the start of a call through this `LibLock`, which ends when the guard is dropped.
+	#[doc(hidden)]
+	#[inline]
+	pub fn enter(&self) -> CallGuard<'_> {
+		// the thread loading the library can't wait on the reload it is running, so its calls
+		// are left to fail in `symbol` instead.
+		if !self.reloadable {
+			return CallGuard::none();
+		}
+		// waiting and tracking the call may clobber the error code the function is about to read.
+		let _last_error = LastErrorGuard::save();
+		if self.check_reentrancy().is_err() {
+			return CallGuard::none();
+		}
+		let addr = self as *const Self as usize;
+		// nested calls can't wait on a reload, since the reload is waiting on them.
+		let nested = ENTERED.with_borrow_mut(|entered| {
+			let nested = entered.contains(&addr);
+			entered.push(addr);
+			nested
+		});
+		loop {
+			self.active.fetch_add(1, Ordering::SeqCst);
+			if nested || !self.reloading.load(Ordering::SeqCst) {
+				break CallGuard(Some(self));
+			}
+			self.leave();
+			let mut gate = self.gate();
+			while self.reloading.load(Ordering::SeqCst) {
+				gate = self.wait_signal(gate);
+			}
+		}
+	}
+
+	// ends a call, waking up a reload waiting for it to return.
+	#[inline]
+	fn leave(&self) {
+		let last = self.active.fetch_sub(1, Ordering::SeqCst) == 1;
+		if last && self.reloading.load(Ordering::SeqCst) {
+			let _gate = self.gate();
+			self.signal.notify_all();
+		}
+	}
+
+	/// Returns the number of times the library has been reloaded, combined with the generation
+	/// of the source backing the `LibLock`, if any.
+	#[doc(hidden)]
+	#[inline]
+	pub fn generation(&self) -> usize {
+		let generation = self.generation.load(Ordering::Acquire);
+		match self.source {
+			Some(Source(source)) => generation.wrapping_add(source.generation()),
+			None => generation,
 		}
 	}
 
 	/// May block if another thread is currently attempting to initialize the cell.
 	///
-	/// This will lazily initialize the LibLock.
+	/// This will lazily initialize the LibLock. Symbols are cached after they are first
+	/// resolved, so repeated lookups of the same name don't go through the system loader.
 	///
 	/// # Errors
 	///
@@ -47,10 +361,6 @@ impl<'a> LibLock<'a> {
 	///
 	/// If the requested symbol does not exist in the dynamic library, then this call will return an error.
 	///
-	/// # Panics
-	///
-	/// Panics if library cannot be initialized
-	///
 	/// # Examples
 	///
 	/// ```no_run
@@ -62,26 +372,317 @@ impl<'a> LibLock<'a> {
 	/// let my_symbol: unsafe extern "C" fn() = unsafe {mem::transmute(sym)};
 	/// ```
 	pub fn symbol(&self, name: &str) -> io::Result<*const Symbol> {
-		let lib = self.hlib.get_or_init(|| {
-			if self.libs.is_empty() {
-				Library::this()
-			} else {
-				self.libs
-					.iter()
-					.find_map(|path| Library::open(path).ok())
-					.unwrap()
+		if let Some(Source(source)) = self.source {
+			return source.symbol(name);
+		}
+		// checked before entering, which would otherwise wait on the reload this thread is running.
+		self.check_reentrancy()?;
+		// the guard keeps a reload from clearing the cache while it's being read.
+		let _guard = self.enter();
+		if let Some(addr) = self.cache.get(name) {
+			return Ok(addr);
+		}
+		let addr = self.resolve(name)?;
+		self.cache.insert(name, addr);
+		Ok(addr)
+	}
+
+	fn resolve(&self, name: &str) -> io::Result<*const Symbol> {
+		if let Some(lib) = self.read().as_ref() {
+			return lib.symbol(name);
+		}
+		let hlib = self.init()?;
+		hlib.as_ref().unwrap().symbol(name)
+	}
+
+	// opens the library if it isn't open yet.
+	fn init(&self) -> io::Result<RwLockWriteGuard<'_, Option<Library>>> {
+		self.check_reentrancy()?;
+		let mut hlib = self.write();
+		if hlib.is_none() {
+			self.open(&mut hlib)?;
+		}
+		Ok(hlib)
+	}
+
+	// loads the library into `hlib`, keeping track of the state.
+	fn open(&self, hlib: &mut Option<Library>) -> io::Result<()> {
+		let _loading = LoadingGuard::new(self);
+		self.state
+			.store(LoadState::Loading as u8, Ordering::Release);
+		self.overridden.store(false, Ordering::Release);
+		self.candidate.store(usize::MAX, Ordering::Release);
+		match self.load() {
+			Ok(lib) => {
+				*hlib = Some(lib);
+				self.state.store(LoadState::Loaded as u8, Ordering::Release);
+				Ok(())
+			}
+			Err(err) => {
+				self.state.store(LoadState::Failed as u8, Ordering::Release);
+				Err(err)
 			}
+		}
+	}
+
+	/// Starts loading the library on a background thread, and returns immediately.
+	///
+	/// Calls to [`symbol`](LibLock::symbol), and to [`dylink`](crate::dylink) functions, that
+	/// arrive while the library is being loaded block until loading finishes, rather than loading
+	/// it a second time. Has no effect if the library is already loaded.
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// # use dylink::*;
+	/// static VULKAN: sync::LibLock = sync::LibLock::new(&["libvulkan.so.1"]);
+	///
+	/// VULKAN.preload();
+	/// // ...other startup work...
+	/// VULKAN.wait().unwrap();
+	/// ```
+	pub fn preload(&'static self) {
+		if !self.is_ready() {
+			thread::spawn(move || {
+				let _ = self.init().map(drop);
+			});
+		}
+	}
+
+	/// Blocks until the library is loaded.
+	///
+	/// If the library isn't being [preloaded](LibLock::preload), it is loaded on the calling thread.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the library failed to load.
+	pub fn wait(&self) -> io::Result<()> {
+		if self.is_ready() || self.source.is_some() {
+			Ok(())
+		} else {
+			self.init().map(drop)
+		}
+	}
+
+	/// Resolves every function declared through [`dylink`](crate::dylink) with this `LibLock`
+	/// as its library, and returns the full list of symbols that couldn't be resolved.
+	///
+	/// This allows missing symbols to be detected at startup, rather than on their first call.
+	/// Resolved addresses are cached, so declared functions don't need to resolve them again.
+	///
+	/// Declarations register themselves through a static constructor, which is supported on
+	/// Windows, MacOS, and ELF based platforms such as Linux. On other platforms no functions are
+	/// registered.
+	///
+	/// # Errors
+	///
+	/// Returns the name of each symbol that couldn't be resolved, along with the reason.
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// # use dylink::*;
+	/// static VULKAN: sync::LibLock = sync::LibLock::new(&["libvulkan.so.1"]);
+	///
+	/// #[dylink(library = VULKAN)]
+	/// extern "system" {
+	///     fn vkGetInstanceProcAddr(instance: usize, name: *const std::ffi::c_char) -> usize;
+	/// }
+	///
+	/// if let Err(missing) = VULKAN.preload_declared() {
+	///     for (name, err) in missing {
+	///         eprintln!("missing `{name}`: {err}");
+	///     }
+	/// }
+	/// ```
+	pub fn preload_declared(&self) -> Result<(), Vec<(&'static str, io::Error)>> {
+		let missing: Vec<_> = crate::thunk::declarations()
+			.filter(|decl| decl.is_bound_to(self))
+			.filter_map(|decl| decl.resolve().err().map(|err| (decl.name(), err)))
+			.collect();
+		if missing.is_empty() {
+			Ok(())
+		} else {
+			Err(missing)
+		}
+	}
+
+	/// Returns `true` if the library is loaded. This method never blocks.
+	#[inline]
+	pub fn is_ready(&self) -> bool {
+		if self.source.is_some() {
+			return true;
+		}
+		match self.hlib.try_read() {
+			Ok(hlib) => hlib.is_some(),
+			Err(TryLockError::Poisoned(err)) => err.into_inner().is_some(),
+			Err(TryLockError::WouldBlock) => false,
+		}
+	}
+
+	/// Returns the state of the library. This method never blocks.
+	///
+	/// # Examples
+	///
+	/// ```rust
+	/// # use dylink::*;
+	/// static FOO: sync::LibLock = sync::LibLock::new(&["libfoo.so"]);
+	///
+	/// assert_eq!(FOO.state(), sync::LoadState::Uninit);
+	/// ```
+	#[inline]
+	pub fn state(&self) -> LoadState {
+		if self.source.is_some() {
+			return LoadState::Loaded;
+		}
+		LoadState::from_u8(self.state.load(Ordering::Acquire))
+	}
+
+	/// Returns the index and path of the candidate the library was loaded from. This method never blocks.
+	///
+	/// The index refers to the paths given to [`LibLock::new`], or to the paths in the
+	/// [override variable](LibLock::env_override) if [`is_overridden`](LibLock::is_overridden) returns `true`.
+	///
+	/// Returns `None` if the library isn't loaded, or wasn't loaded from a path, such as when it is
+	/// the running process or was opened by a [loader](LibLock::with_loader).
+	pub fn loaded_candidate(&self) -> Option<(usize, &path::Path)> {
+		if self.state() != LoadState::Loaded {
+			return None;
+		}
+		let index = self.candidate.load(Ordering::Acquire);
+		let path = if self.is_overridden() {
+			self.env_paths.get()?.get(index)?.as_path()
+		} else {
+			path::Path::new(self.libs.get(index)?)
+		};
+		Some((index, path))
+	}
+
+	/// Returns the symbols that have been resolved through [`symbol`](LibLock::symbol) so far,
+	/// along with their addresses.
+	///
+	/// The cache is emptied whenever the library is [reloaded](LibLock::reload) or [taken](LibLock::take).
+	pub fn cached_symbols(&self) -> Vec<(String, *const Symbol)> {
+		let _guard = self.enter();
+		self.cache.entries()
+	}
+
+	fn load(&self) -> io::Result<Library> {
+		let env_paths = self.env_paths.get_or_init(|| {
+			self.env
+				.and_then(env::var_os)
+				.map_or_else(Vec::new, |paths| {
+					env::split_paths(&paths)
+						.filter(|path| !path.as_os_str().is_empty())
+						.collect()
+				})
 		});
-		lib.symbol(name)
+		if !env_paths.is_empty() {
+			let lib = self.open_any(env_paths)?;
+			self.overridden.store(true, Ordering::Release);
+			Ok(lib)
+		} else if let Some(loader) = self.loader {
+			loader().and_then(|lib| self.validate(lib))
+		} else if self.libs.is_empty() {
+			self.validate(Library::this())
+		} else {
+			self.open_any(self.libs)
+		}
+	}
+
+	// tries each path in order, returning the first library accepted or the last error.
+	fn open_any<P: AsRef<path::Path>>(&self, paths: &[P]) -> io::Result<Library> {
+		let mut error = io::Error::new(io::ErrorKind::NotFound, "no library paths to try");
+		for (index, path) in paths.iter().enumerate() {
+			match Library::open(path).and_then(|lib| self.validate(lib)) {
+				Ok(lib) => {
+					self.candidate.store(index, Ordering::Release);
+					return Ok(lib);
+				}
+				Err(err) => error = err,
+			}
+		}
+		Err(error)
+	}
+
+	// the library is locked while it is loaded, so a thread calling back into the `LibLock`
+	// during loading, such as through a library constructor, would deadlock.
+	fn check_reentrancy(&self) -> io::Result<()> {
+		let addr = self as *const Self as usize;
+		if LOADING.with_borrow(|loading| loading.contains(&addr)) {
+			Err(io::Error::new(
+				io::ErrorKind::WouldBlock,
+				ReentrantError {
+					library: self.describe(),
+				},
+			))
+		} else {
+			Ok(())
+		}
+	}
+
+	// a human readable name for error messages.
+	fn describe(&self) -> String {
+		if let Some(var) = self.env.filter(|_| self.is_overridden()) {
+			format!("${var}")
+		} else if self.source.is_some() {
+			String::from("<source>")
+		} else if self.loader.is_some() {
+			String::from("<loader>")
+		} else if self.libs.is_empty() {
+			String::from("<this process>")
+		} else {
+			self.libs.join(", ")
+		}
+	}
+
+	#[inline]
+	fn validate(&self, lib: Library) -> io::Result<Library> {
+		match self.on_load {
+			Some(on_load) => on_load(&lib).map(|()| lib),
+			None => Ok(lib),
+		}
 	}
+
+	#[inline]
+	fn read(&self) -> RwLockReadGuard<'_, Option<Library>> {
+		self.hlib.read().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	#[inline]
+	fn write(&self) -> RwLockWriteGuard<'_, Option<Library>> {
+		self.hlib.write().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	#[inline]
+	fn gate(&self) -> MutexGuard<'_, ()> {
+		self.gate.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	#[inline]
+	fn wait_signal<'g>(&self, gate: MutexGuard<'g, ()>) -> MutexGuard<'g, ()> {
+		self.signal
+			.wait(gate)
+			.unwrap_or_else(PoisonError::into_inner)
+	}
+
 	/// Gets the reference to the underlying value.
 	///
 	/// Returns `None` if the cell is empty, or being initialized. This
 	/// method never blocks.
+	///
+	/// Always returns `None` for a [reloadable](LibLock::reloadable) `LibLock`, since its library
+	/// may be closed while the reference is held.
 	#[cfg(feature = "unstable")]
 	#[inline]
 	pub fn get(&self) -> Option<&Library> {
-		self.hlib.get()
+		if self.reloadable {
+			return None;
+		}
+		let hlib = self.hlib.try_read().ok()?;
+		let lib: *const Library = hlib.as_ref()?;
+		// SAFETY: without reloads, the library is only replaced through `&mut self`.
+		Some(unsafe { &*lib })
 	}
 	/// Takes the value out of this `LibLock`, moving it back to an uninitialized state.
 	///
@@ -90,19 +691,137 @@ impl<'a> LibLock<'a> {
 	/// Safety is guaranteed by requiring a mutable reference.
 	#[inline]
 	pub fn take(&mut self) -> Option<Library> {
-		self.hlib.take()
+		*self.overridden.get_mut() = false;
+		*self.state.get_mut() = LoadState::Uninit as u8;
+		*self.candidate.get_mut() = usize::MAX;
+		self.env_paths.take();
+		*self.generation.get_mut() += 1;
+		unsafe { self.cache.clear() };
+		self.hlib
+			.get_mut()
+			.unwrap_or_else(PoisonError::into_inner)
+			.take()
 	}
 
 	#[cfg(feature = "unstable")]
 	#[inline]
 	pub fn set(&self, value: Library) -> Result<(), Library> {
-		self.hlib.set(value)
+		let mut hlib = self.write();
+		if hlib.is_some() {
+			Err(value)
+		} else {
+			*hlib = Some(value);
+			Ok(())
+		}
 	}
 
 	/// Consumes the `LibLock`, returning the `Library`.
 	#[cfg(feature = "unstable")]
 	#[inline]
 	pub fn into_inner(self) -> Option<Library> {
-		self.hlib.into_inner()
+		self.hlib
+			.into_inner()
+			.unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+#[derive(Clone, Copy)]
+struct Source<'a>(&'a dyn SymbolSource);
+
+impl fmt::Debug for Source<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("SymbolSource")
+	}
+}
+
+/// The error returned when a [`LibLock`] is used by the same thread that is loading it.
+///
+/// This happens when a library's initialization routine, or an [`on_load`](LibLock::on_load)
+/// hook, calls back into the `LibLock` that is loading the library. The error is wrapped in
+/// an [`io::Error`] of kind [`WouldBlock`](io::ErrorKind::WouldBlock).
+///
+/// # Examples
+///
+/// ```no_run
+/// # use dylink::*;
+/// static FOO: sync::LibLock = sync::LibLock::new(&["libfoo.so"]);
+///
+/// if let Err(err) = FOO.symbol("foo") {
+///     if let Some(reentrant) = err.get_ref().and_then(|err| err.downcast_ref::<sync::ReentrantError>()) {
+///         eprintln!("{} was used while loading", reentrant.library());
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReentrantError {
+	library: String,
+}
+
+impl ReentrantError {
+	/// Returns the name of the library that was being loaded.
+	#[inline]
+	pub fn library(&self) -> &str {
+		&self.library
+	}
+}
+
+impl fmt::Display for ReentrantError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"`{}` was used while it was being loaded by the same thread",
+			self.library
+		)
+	}
+}
+
+impl error::Error for ReentrantError {}
+
+// marks a lock as being loaded by the current thread until dropped.
+struct LoadingGuard(usize);
+
+impl LoadingGuard {
+	fn new(lock: &LibLock) -> Self {
+		let addr = lock as *const LibLock as usize;
+		LOADING.with_borrow_mut(|loading| loading.push(addr));
+		Self(addr)
+	}
+}
+
+impl Drop for LoadingGuard {
+	fn drop(&mut self) {
+		LOADING.with_borrow_mut(|loading| {
+			if let Some(index) = loading.iter().rposition(|&elem| elem == self.0) {
+				loading.remove(index);
+			}
+		});
+	}
+}
+
+/// Tracks a call made through a reloadable [`LibLock`].
+#[doc(hidden)]
+pub struct CallGuard<'a>(Option<&'a LibLock<'a>>);
+
+impl CallGuard<'_> {
+	#[inline]
+	pub(crate) const fn none() -> Self {
+		Self(None)
+	}
+}
+
+impl Drop for CallGuard<'_> {
+	#[inline]
+	fn drop(&mut self) {
+		if let Some(lock) = self.0 {
+			// the function may have just set the error code.
+			let _last_error = LastErrorGuard::save();
+			let addr = lock as *const LibLock as usize;
+			ENTERED.with_borrow_mut(|entered| {
+				if let Some(index) = entered.iter().rposition(|&elem| elem == addr) {
+					entered.remove(index);
+				}
+			});
+			lock.leave();
+		}
 	}
 }
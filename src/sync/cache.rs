@@ -0,0 +1,100 @@
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::Symbol;
+
+const BUCKET_COUNT: usize = 64;
+
+struct Entry {
+	name: Box<str>,
+	addr: *const Symbol,
+	next: *mut Entry,
+}
+
+// A name to address map that can be read without locking.
+//
+// Entries are only ever prepended to a bucket, so readers can walk a bucket while it is
+// being written to. Entries are freed by `clear`, which requires that there are no readers.
+pub(super) struct SymbolCache {
+	buckets: [AtomicPtr<Entry>; BUCKET_COUNT],
+}
+
+impl SymbolCache {
+	pub const fn new() -> Self {
+		Self {
+			buckets: [const { AtomicPtr::new(ptr::null_mut()) }; BUCKET_COUNT],
+		}
+	}
+
+	// FNV-1a, which is good enough for symbol names.
+	fn bucket(&self, name: &str) -> &AtomicPtr<Entry> {
+		let hash = name.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+			(hash ^ byte as u64).wrapping_mul(0x100000001b3)
+		});
+		&self.buckets[hash as usize % BUCKET_COUNT]
+	}
+
+	pub fn get(&self, name: &str) -> Option<*const Symbol> {
+		let mut entry = self.bucket(name).load(Ordering::Acquire);
+		while let Some(elem) = unsafe { entry.as_ref() } {
+			if &*elem.name == name {
+				return Some(elem.addr);
+			}
+			entry = elem.next;
+		}
+		None
+	}
+
+	pub fn insert(&self, name: &str, addr: *const Symbol) {
+		let bucket = self.bucket(name);
+		let entry = Box::into_raw(Box::new(Entry {
+			name: name.into(),
+			addr,
+			next: bucket.load(Ordering::Acquire),
+		}));
+		// racing inserts of the same name may leave a duplicate, which is harmless.
+		while let Err(head) = bucket.compare_exchange_weak(
+			unsafe { (*entry).next },
+			entry,
+			Ordering::AcqRel,
+			Ordering::Acquire,
+		) {
+			unsafe { (*entry).next = head };
+		}
+	}
+
+	pub fn entries(&self) -> Vec<(String, *const Symbol)> {
+		let mut entries = Vec::new();
+		for bucket in &self.buckets {
+			let mut entry = bucket.load(Ordering::Acquire);
+			while let Some(elem) = unsafe { entry.as_ref() } {
+				entries.push((elem.name.to_string(), elem.addr));
+				entry = elem.next;
+			}
+		}
+		entries
+	}
+
+	// Safety: there must not be any concurrent readers.
+	pub unsafe fn clear(&self) {
+		for bucket in &self.buckets {
+			let mut entry = bucket.swap(ptr::null_mut(), Ordering::AcqRel);
+			while !entry.is_null() {
+				let elem = Box::from_raw(entry);
+				entry = elem.next;
+			}
+		}
+	}
+}
+
+impl Drop for SymbolCache {
+	fn drop(&mut self) {
+		unsafe { self.clear() }
+	}
+}
+
+impl std::fmt::Debug for SymbolCache {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_map().entries(self.entries()).finish()
+	}
+}
@@ -0,0 +1,407 @@
+//! Support code for functions generated by [`dylink`](crate::dylink).
+
+use std::ffi::{c_void, CStr, CString};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "testing")]
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+use std::{fmt, io, ptr};
+
+use crate::hooks::{self, CallEvent, ResolveEvent};
+use crate::sync::CallGuard;
+use crate::{imp, Symbol, SymbolSource};
+
+// head of the intrusive list of registered declarations.
+static REGISTRY: AtomicPtr<Declaration> = AtomicPtr::new(ptr::null_mut());
+
+/// The cached address of a generated function.
+#[derive(Debug)]
+pub struct Thunk {
+	addr: AtomicPtr<Symbol>,
+	// the source generation `addr` was resolved in.
+	generation: AtomicUsize,
+	// an address used instead of `addr` if non-null.
+	#[cfg(feature = "testing")]
+	overridden: AtomicPtr<Symbol>,
+}
+
+impl Thunk {
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			addr: AtomicPtr::new(ptr::null_mut()),
+			generation: AtomicUsize::new(0),
+			#[cfg(feature = "testing")]
+			overridden: AtomicPtr::new(ptr::null_mut()),
+		}
+	}
+
+	/// Replaces the address returned by [`Thunk::resolve`] until cleared, returning the previous
+	/// override. A null `addr` clears the override.
+	#[cfg(feature = "testing")]
+	#[inline]
+	pub fn set_override(&self, addr: *const Symbol) -> *const Symbol {
+		self.overridden.swap(addr.cast_mut(), Ordering::AcqRel)
+	}
+
+	/// Overrides the address until the returned guard is dropped, which restores the previous
+	/// override.
+	#[cfg(feature = "testing")]
+	#[inline]
+	pub fn override_with(&self, addr: *const Symbol) -> OverrideGuard<'_> {
+		OverrideGuard {
+			thunk: self,
+			previous: self.set_override(addr),
+		}
+	}
+
+	/// Returns the address set by [`Thunk::set_override`], or null if there isn't one.
+	#[cfg(feature = "testing")]
+	#[inline]
+	pub fn overridden(&self) -> *const Symbol {
+		self.overridden.load(Ordering::Acquire)
+	}
+
+	/// Returns the cached address, resolving the first of `names` found in `source` if there
+	/// isn't one. If none are found, the error of the first name is returned.
+	///
+	/// The caller is expected to hold the guard returned by [`SymbolSource::enter`].
+	#[inline]
+	pub fn resolve<S: SymbolSource + ?Sized>(
+		&self,
+		source: &S,
+		names: &[&str],
+	) -> io::Result<*const Symbol> {
+		#[cfg(feature = "testing")]
+		{
+			let addr = self.overridden.load(Ordering::Acquire);
+			if !addr.is_null() {
+				return Ok(addr);
+			}
+		}
+		let generation = source.generation();
+		if self.generation.load(Ordering::Acquire) == generation {
+			let addr = self.addr.load(Ordering::Relaxed);
+			if !addr.is_null() {
+				return Ok(addr);
+			}
+		}
+		// loading may clobber the error code the function is about to read or set.
+		let _last_error = LastErrorGuard::save();
+		let mut first_err = None;
+		let mut addr = None;
+		for name in names {
+			match source.symbol(name) {
+				Ok(symbol) => {
+					addr = Some(symbol);
+					break;
+				}
+				Err(err) => {
+					first_err.get_or_insert(err);
+				}
+			}
+		}
+		let addr = match (addr, first_err) {
+			(Some(addr), _) => addr,
+			(None, Some(err)) => return Err(err),
+			(None, None) => {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					"no symbol names to resolve",
+				))
+			}
+		};
+		self.addr.store(addr.cast_mut(), Ordering::Relaxed);
+		self.generation.store(generation, Ordering::Release);
+		Ok(addr)
+	}
+
+	/// Returns `true` if an address is cached for the current generation of `source`.
+	#[inline]
+	fn is_cached<S: SymbolSource + ?Sized>(&self, source: &S) -> bool {
+		#[cfg(feature = "testing")]
+		if !self.overridden.load(Ordering::Acquire).is_null() {
+			return true;
+		}
+		self.generation.load(Ordering::Acquire) == source.generation()
+			&& !self.addr.load(Ordering::Relaxed).is_null()
+	}
+
+	/// Like [`Thunk::resolve`], but caches and returns `fallback` if none of `names` can be resolved.
+	#[inline]
+	pub fn resolve_or<S: SymbolSource + ?Sized>(
+		&self,
+		source: &S,
+		names: &[&str],
+		fallback: *const Symbol,
+	) -> *const Symbol {
+		self.resolve(source, names).unwrap_or_else(|_| {
+			self.addr.store(fallback.cast_mut(), Ordering::Relaxed);
+			self.generation
+				.store(source.generation(), Ordering::Release);
+			fallback
+		})
+	}
+}
+
+impl Default for Thunk {
+	#[inline]
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// A thunk shared by a function and its accessors, which is looked up by a key unique to the
+/// expansion of `dylink` that declared them.
+#[cfg(feature = "testing")]
+#[derive(Debug)]
+pub struct SharedThunk {
+	key: &'static str,
+	thunk: AtomicPtr<Thunk>,
+}
+
+#[cfg(feature = "testing")]
+impl SharedThunk {
+	#[inline]
+	pub const fn new(key: &'static str) -> Self {
+		Self {
+			key,
+			thunk: AtomicPtr::new(ptr::null_mut()),
+		}
+	}
+
+	/// Returns the thunk of the key, creating it on first use.
+	#[inline]
+	pub fn get(&self) -> &'static Thunk {
+		let thunk = self.thunk.load(Ordering::Acquire);
+		if let Some(thunk) = unsafe { thunk.as_ref() } {
+			return thunk;
+		}
+		static THUNKS: Mutex<Vec<(&str, &Thunk)>> = Mutex::new(Vec::new());
+		let mut thunks = THUNKS.lock().unwrap_or_else(PoisonError::into_inner);
+		let thunk = match thunks.iter().find(|(key, _)| *key == self.key) {
+			Some((_, thunk)) => *thunk,
+			None => {
+				let thunk = &*Box::leak(Box::new(Thunk::new()));
+				thunks.push((self.key, thunk));
+				thunk
+			}
+		};
+		self.thunk
+			.store((thunk as *const Thunk).cast_mut(), Ordering::Release);
+		thunk
+	}
+}
+
+/// A guard restoring the previous override of a function when dropped.
+///
+/// Returned by the `_override_with` accessors generated by [`dylink`](crate::dylink).
+#[cfg(feature = "testing")]
+#[derive(Debug)]
+#[must_use = "the override is cleared when the guard is dropped"]
+pub struct OverrideGuard<'a> {
+	thunk: &'a Thunk,
+	previous: *const Symbol,
+}
+
+#[cfg(feature = "testing")]
+impl Drop for OverrideGuard<'_> {
+	#[inline]
+	fn drop(&mut self) {
+		self.thunk.set_override(self.previous);
+	}
+}
+
+/// Restores the `errno` of the calling thread when dropped, or its last-error code on Windows.
+pub(crate) struct LastErrorGuard(#[cfg(unix)] std::ffi::c_int, #[cfg(windows)] u32);
+
+impl LastErrorGuard {
+	#[inline]
+	pub(crate) fn save() -> Self {
+		Self(imp::last_error())
+	}
+}
+
+impl Drop for LastErrorGuard {
+	#[inline]
+	fn drop(&mut self) {
+		imp::set_last_error(self.0);
+	}
+}
+
+/// Runs `resolve`, reporting it to the subscriber unless `thunk` already has an address.
+#[inline]
+pub fn trace_resolve<S: SymbolSource + ?Sized>(
+	thunk: &Thunk,
+	source: &S,
+	library: &'static str,
+	symbol: &'static str,
+	resolve: impl FnOnce() -> io::Result<*const Symbol>,
+) -> io::Result<*const Symbol> {
+	let Some(subscriber) = hooks::subscriber().filter(|_| !thunk.is_cached(source)) else {
+		return resolve();
+	};
+	let start = Instant::now();
+	let result = resolve();
+	let _last_error = LastErrorGuard::save();
+	subscriber.on_resolve(&ResolveEvent {
+		library,
+		symbol,
+		result: &result,
+		elapsed: start.elapsed(),
+	});
+	result
+}
+
+/// Runs `call`, reporting it to the subscriber.
+#[inline]
+pub fn trace_call<R>(name: &'static str, call: impl FnOnce() -> R) -> R {
+	let Some(subscriber) = hooks::subscriber() else {
+		return call();
+	};
+	let start = Instant::now();
+	let ret = call();
+	let _last_error = LastErrorGuard::save();
+	subscriber.on_call(&CallEvent {
+		name,
+		elapsed: start.elapsed(),
+	});
+	ret
+}
+
+/// A function declared through `dylink`, which registers itself at startup.
+#[derive(Debug)]
+pub struct Declaration {
+	name: &'static str,
+	// returns the address of the source the function is bound to, to tell sources apart.
+	source: fn() -> *const (),
+	resolve: fn() -> io::Result<*const Symbol>,
+	next: AtomicPtr<Declaration>,
+}
+
+impl Declaration {
+	#[inline]
+	pub const fn new(
+		name: &'static str,
+		source: fn() -> *const (),
+		resolve: fn() -> io::Result<*const Symbol>,
+	) -> Self {
+		Self {
+			name,
+			source,
+			resolve,
+			next: AtomicPtr::new(ptr::null_mut()),
+		}
+	}
+
+	#[inline]
+	pub(crate) fn name(&self) -> &'static str {
+		self.name
+	}
+
+	#[inline]
+	pub(crate) fn is_bound_to<S: SymbolSource + ?Sized>(&self, source: &S) -> bool {
+		ptr::addr_eq((self.source)(), source.addr())
+	}
+
+	#[inline]
+	pub(crate) fn resolve(&self) -> io::Result<*const Symbol> {
+		(self.resolve)()
+	}
+}
+
+/// Adds a declaration to the registry. This is called by a static constructor emitted by `dylink`.
+pub fn register(decl: &'static Declaration) {
+	let decl_ptr = decl as *const Declaration as *mut Declaration;
+	let mut head = REGISTRY.load(Ordering::Acquire);
+	loop {
+		decl.next.store(head, Ordering::Relaxed);
+		match REGISTRY.compare_exchange_weak(head, decl_ptr, Ordering::AcqRel, Ordering::Acquire) {
+			Ok(_) => break,
+			Err(new_head) => head = new_head,
+		}
+	}
+}
+
+/// Returns every registered declaration.
+pub(crate) fn declarations() -> impl Iterator<Item = &'static Declaration> {
+	let head = unsafe { REGISTRY.load(Ordering::Acquire).as_ref() };
+	std::iter::successors(head, |decl| unsafe {
+		decl.next.load(Ordering::Acquire).as_ref()
+	})
+}
+
+/// Requires the `library` argument to be a `'static` source.
+///
+/// Called with method syntax, so that static places are borrowed and `&'static` references are
+/// taken as they are, while temporaries are rejected. Type errors are reported at the argument.
+pub trait StaticSource: SymbolSource {
+	#[inline(always)]
+	fn dylink_source(&'static self) -> &'static Self {
+		self
+	}
+}
+
+impl<S: SymbolSource + ?Sized> StaticSource for S {}
+
+/// A source resolving symbols through a user function, such as `vkGetInstanceProcAddr`.
+///
+/// Used by functions declared with `#[dylink(loader = ...)]`.
+#[derive(Debug, Clone, Copy)]
+pub struct Loader(pub fn(&CStr) -> *const c_void);
+
+impl SymbolSource for Loader {
+	fn symbol(&self, name: &str) -> io::Result<*const Symbol> {
+		let c_name =
+			CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+		let addr = (self.0)(&c_name);
+		if addr.is_null() {
+			Err(io::Error::new(
+				io::ErrorKind::NotFound,
+				format!("symbol `{name}` not found by loader"),
+			))
+		} else {
+			Ok(addr.cast())
+		}
+	}
+}
+
+/// The function pointer of a variadic function, which is tracked as a call through its library
+/// until dropped.
+///
+/// Returned by the accessors `dylink` generates for variadic functions. Calling through it in
+/// the same statement, as in `printf()(format, ...)`, keeps a reloadable
+/// [`LibLock`](crate::sync::LibLock) from closing the library during the call.
+pub struct Variadic<'a, F> {
+	pfn: F,
+	_guard: CallGuard<'a>,
+}
+
+impl<'a, F> Variadic<'a, F> {
+	#[inline]
+	pub fn new(pfn: F, guard: CallGuard<'a>) -> Self {
+		Self { pfn, _guard: guard }
+	}
+
+	/// Wraps a statically linked function, which doesn't need to be tracked.
+	#[inline]
+	pub fn linked(pfn: F) -> Self {
+		Self::new(pfn, CallGuard::none())
+	}
+}
+
+impl<F> Deref for Variadic<'_, F> {
+	type Target = F;
+
+	#[inline]
+	fn deref(&self) -> &F {
+		&self.pfn
+	}
+}
+
+impl<F> fmt::Debug for Variadic<'_, F> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Variadic").finish_non_exhaustive()
+	}
+}
@@ -32,6 +32,7 @@ impl Weak {
 	/// let empty: Weak = Weak::new();
 	/// assert!(empty.upgrade().is_none());
 	/// ```
+	#[allow(clippy::new_without_default)]
 	#[inline]
 	pub const fn new() -> Self {
 		Self {
@@ -0,0 +1,31 @@
+#![cfg(target_os = "linux")]
+// Setting environment variables isn't thread-safe, so this test is kept in its own binary, where
+// no other test can be reading the environment at the same time.
+use dylink::*;
+
+#[test]
+fn test_env_override() {
+	static LIB: sync::LibLock =
+		sync::LibLock::new(&["libdoesnotexist.so"]).env_override("DYLINK_TEST_ENV_OVERRIDE");
+	std::env::set_var(
+		"DYLINK_TEST_ENV_OVERRIDE",
+		"libdoesnotexist.so.1:libX11.so.6",
+	);
+
+	assert!(!LIB.is_overridden());
+	let sym = LIB.symbol("XOpenDisplay").unwrap();
+	assert!(!sym.is_null());
+	assert!(LIB.is_overridden());
+	let (index, path) = LIB.loaded_candidate().unwrap();
+	assert_eq!(index, 1);
+	assert_eq!(path, std::path::Path::new("libX11.so.6"));
+
+	// the variable is read again once the library is taken.
+	let mut lib = sync::LibLock::new(&["libc.so.6"]).env_override("DYLINK_TEST_ENV_OVERRIDE");
+	assert!(lib.symbol("XOpenDisplay").is_ok());
+	assert!(lib.is_overridden());
+	lib.take();
+	std::env::remove_var("DYLINK_TEST_ENV_OVERRIDE");
+	assert!(lib.symbol("atoi").is_ok());
+	assert!(!lib.is_overridden());
+}
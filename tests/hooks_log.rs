@@ -0,0 +1,45 @@
+#![cfg(all(feature = "log", not(feature = "tracing")))]
+// Events are only forwarded while no subscriber is installed, so this test is kept in its own
+// binary.
+use dylink::*;
+use std::sync::Mutex;
+
+struct Capture(Mutex<Vec<String>>);
+
+impl log::Log for Capture {
+	fn enabled(&self, metadata: &log::Metadata) -> bool {
+		metadata.target() == "dylink"
+	}
+
+	fn log(&self, record: &log::Record) {
+		if self.enabled(record.metadata()) {
+			self.0.lock().unwrap().push(record.args().to_string());
+		}
+	}
+
+	fn flush(&self) {}
+}
+
+static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+#[test]
+fn test_log_forwarding() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = MOCK, trace)]
+	extern "C-unwind" fn logged(x: u32) -> u32;
+
+	extern "C-unwind" fn fake_logged(x: u32) -> u32 {
+		x
+	}
+
+	log::set_logger(&CAPTURE).unwrap();
+	log::set_max_level(log::LevelFilter::Trace);
+	MOCK.insert("logged", fake_logged as *const Symbol);
+	assert_eq!(unsafe { logged(1) }, 1);
+
+	let records = CAPTURE.0.lock().unwrap();
+	assert_eq!(records.len(), 2);
+	assert!(records[0].starts_with("resolved `logged` from `MOCK`"));
+	assert!(records[1].starts_with("called `logged`"));
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "tracing")]
+// Events are only forwarded while no subscriber is installed, so this test is kept in its own
+// binary.
+use dylink::*;
+use std::fmt;
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata};
+
+// records the message and symbol of each event in `EVENTS`.
+struct Capture;
+
+static EVENTS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+struct Fields<'a>(&'a mut (String, String));
+
+impl Visit for Fields<'_> {
+	fn record_str(&mut self, field: &Field, value: &str) {
+		if field.name() == "symbol" || field.name() == "name" {
+			self.0 .1 = value.to_string();
+		}
+	}
+
+	fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+		if field.name() == "message" {
+			self.0 .0 = format!("{value:?}");
+		}
+	}
+}
+
+impl tracing::Subscriber for Capture {
+	fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+		metadata.target() == "dylink"
+	}
+
+	fn new_span(&self, _: &Attributes<'_>) -> Id {
+		Id::from_u64(1)
+	}
+
+	fn record(&self, _: &Id, _: &Record<'_>) {}
+
+	fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+	fn event(&self, event: &Event<'_>) {
+		let mut fields = Default::default();
+		event.record(&mut Fields(&mut fields));
+		EVENTS.lock().unwrap().push(fields);
+	}
+
+	fn enter(&self, _: &Id) {}
+
+	fn exit(&self, _: &Id) {}
+}
+
+#[test]
+fn test_tracing_forwarding() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = MOCK, trace)]
+	extern "C-unwind" fn traced(x: u32) -> u32;
+
+	extern "C-unwind" fn fake_traced(x: u32) -> u32 {
+		x
+	}
+
+	tracing::subscriber::set_global_default(Capture).unwrap();
+	MOCK.insert("traced", fake_traced as *const Symbol);
+	assert_eq!(unsafe { traced(1) }, 1);
+
+	let events = EVENTS.lock().unwrap();
+	assert_eq!(
+		*events,
+		[
+			("resolved symbol".to_string(), "traced".to_string()),
+			("called function".to_string(), "traced".to_string()),
+		]
+	);
+}
@@ -102,3 +102,547 @@ fn test_downgrade_upgrade() {
 
 	assert!(strong_clone.is_some());
 }
+
+#[test]
+fn test_mock_library() {
+	static MOCK: MockLibrary = MockLibrary::new();
+	static LIB: sync::LibLock = sync::LibLock::with_source(&MOCK);
+
+	#[dylink(library = LIB)]
+	extern "C-unwind" {
+		fn add(a: i32, b: i32) -> i32;
+	}
+
+	extern "C-unwind" fn fake_add(a: i32, b: i32) -> i32 {
+		a + b
+	}
+
+	assert!(LIB.symbol("add").is_err());
+	assert!(MOCK.insert("add", fake_add as *const Symbol).is_none());
+	assert!(LIB.is_ready());
+	assert_eq!(unsafe { add(2, 3) }, 5);
+	assert_eq!(MOCK.remove("add"), Some(fake_add as *const Symbol));
+	assert!(MOCK.symbol("add").is_err());
+}
+
+#[test]
+fn test_mock_library_changes() {
+	static MOCK: MockLibrary = MockLibrary::new();
+	static LIB: sync::LibLock = sync::LibLock::with_source(&MOCK);
+
+	#[dylink(library = MOCK, try_fn)]
+	extern "C-unwind" {
+		fn answer() -> u32;
+	}
+
+	#[dylink(library = LIB, try_fn)]
+	extern "C-unwind" fn locked_answer() -> u32;
+
+	extern "C-unwind" fn one() -> u32 {
+		1
+	}
+	extern "C-unwind" fn two() -> u32 {
+		2
+	}
+
+	MOCK.insert("answer", one as *const Symbol);
+	MOCK.insert("locked_answer", one as *const Symbol);
+	assert_eq!(unsafe { answer() + locked_answer() }, 2);
+	MOCK.insert("answer", two as *const Symbol);
+	MOCK.insert("locked_answer", two as *const Symbol);
+	assert_eq!(unsafe { answer() + locked_answer() }, 4);
+	MOCK.remove("answer");
+	MOCK.remove("locked_answer");
+	assert!(unsafe { try_answer() }.is_err());
+	assert!(unsafe { try_locked_answer() }.is_err());
+}
+
+#[test]
+fn test_mock_closures() {
+	use std::sync::atomic::{AtomicU32, Ordering};
+	use std::sync::Arc;
+
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = MOCK)]
+	extern "C-unwind" {
+		fn add(a: u32, b: u32) -> u32;
+		fn record(value: u32);
+	}
+
+	let offset = 10;
+	MOCK.insert_fn("add", move |a: u32, b: u32| a + b + offset);
+	let total = Arc::new(AtomicU32::new(0));
+	let recorded = Arc::clone(&total);
+	MOCK.insert_fn("record", move |value: u32| {
+		recorded.fetch_add(value, Ordering::Relaxed);
+	});
+
+	assert_eq!(unsafe { add(1, 2) }, 13);
+	unsafe {
+		record(4);
+		record(5);
+	}
+	assert_eq!(total.load(Ordering::Relaxed), 9);
+}
+
+#[test]
+fn test_mock_closures_per_name() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = MOCK)]
+	extern "C-unwind" {
+		fn version_major() -> u32;
+		fn version_minor() -> u32;
+	}
+
+	fn constant(value: u32) -> impl Fn() -> u32 + Send + Sync + 'static {
+		move || value
+	}
+
+	for (name, value) in [("version_major", 1), ("version_minor", 2)] {
+		MOCK.insert_fn(name, move || value);
+	}
+	assert_eq!(unsafe { version_major() }, 1);
+	assert_eq!(unsafe { version_minor() }, 2);
+
+	// another library with the same closure type doesn't interfere.
+	let other = MockLibrary::new();
+	other.insert_fn("version_major", constant(3));
+	MOCK.insert_fn("version_major", constant(4));
+	let pfn: extern "C-unwind" fn() -> u32 =
+		unsafe { std::mem::transmute(other.symbol("version_major").unwrap()) };
+	assert_eq!(pfn(), 3);
+	assert_eq!(unsafe { version_major() }, 4);
+	assert_eq!(unsafe { version_minor() }, 2);
+}
+
+#[test]
+fn test_try_fn() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = MOCK, try_fn)]
+	extern "C-unwind" {
+		fn optional_entry(x: u32) -> u32;
+	}
+
+	extern "C-unwind" fn fake_entry(x: u32) -> u32 {
+		x * 2
+	}
+
+	let err = unsafe { try_optional_entry(4) }.unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+	MOCK.insert("optional_entry", fake_entry as *const Symbol);
+	assert_eq!(unsafe { try_optional_entry(4) }.unwrap(), 8);
+	assert_eq!(unsafe { optional_entry(5) }, 10);
+}
+
+#[test]
+fn test_companion() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = MOCK)]
+	extern "C-unwind" {
+		fn double(x: u32) -> u32;
+	}
+
+	extern "C-unwind" fn fake_double(x: u32) -> u32 {
+		x * 2
+	}
+
+	assert!(!double_is_available());
+	assert!(double_ptr().is_none());
+	MOCK.insert("double", fake_double as *const Symbol);
+	assert!(double_is_available());
+	let pfn: PFN_double = double_ptr().unwrap();
+	assert_eq!(unsafe { pfn(3) }, 6);
+	assert_eq!(unsafe { double(4) }, 8);
+}
+
+#[test]
+fn test_same_named_type() {
+	use std::ffi::{c_char, c_int};
+
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	// C APIs often give a function and a struct the same name.
+	#[allow(non_camel_case_types)]
+	#[repr(C)]
+	struct stat {
+		size: u64,
+	}
+
+	#[dylink(library = MOCK)]
+	extern "C-unwind" {
+		fn stat(path: *const c_char, buf: *mut stat) -> c_int;
+	}
+
+	extern "C-unwind" fn fake_stat(_: *const c_char, buf: *mut stat) -> c_int {
+		unsafe { (*buf).size = 7 };
+		0
+	}
+
+	MOCK.insert("stat", fake_stat as *const Symbol);
+	assert!(stat_is_available());
+	let mut buf = stat { size: 0 };
+	assert_eq!(unsafe { stat(c"file".as_ptr(), &mut buf) }, 0);
+	assert_eq!(buf.size, 7);
+}
+
+#[test]
+fn test_preload_declared() {
+	static MOCK: MockLibrary = MockLibrary::new();
+	static LIB: sync::LibLock = sync::LibLock::with_source(&MOCK);
+
+	#[dylink(library = LIB)]
+	extern "C-unwind" {
+		fn present();
+		fn absent();
+	}
+
+	extern "C-unwind" fn fake_present() {}
+
+	MOCK.insert("present", fake_present as *const Symbol);
+	let missing = LIB.preload_declared().unwrap_err();
+	assert_eq!(missing.len(), 1);
+	assert_eq!(missing[0].0, "absent");
+	assert!(present_is_available());
+	assert!(!absent_is_available());
+
+	MOCK.insert("absent", fake_present as *const Symbol);
+	assert!(LIB.preload_declared().is_ok());
+}
+
+#[test]
+fn test_fallback() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	extern "C-unwind" fn shim_double(x: u32) -> u32 {
+		x * 2
+	}
+
+	#[dylink(library = MOCK, fallback = shim_double)]
+	extern "C-unwind" fn double(x: u32) -> u32;
+
+	#[dylink(library = MOCK)]
+	extern "C-unwind" {
+		#[dylink(fallback = shim_double)]
+		fn twice(x: u32) -> u32;
+	}
+
+	assert_eq!(unsafe { double(4) }, 8);
+	assert_eq!(unsafe { twice(5) }, 10);
+	assert!(!twice_is_available());
+	assert!(twice_ptr().is_some());
+}
+
+#[test]
+fn test_link_name_candidates() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = MOCK, link_name = ["bindVertexArray", "bindVertexArrayARB", "bindVertexArrayAPPLE"])]
+	extern "C-unwind" fn bind_vertex_array(array: u32) -> u32;
+
+	extern "C-unwind" fn fake_bind(array: u32) -> u32 {
+		array + 1
+	}
+
+	MOCK.insert("bindVertexArrayARB", fake_bind as *const Symbol);
+	assert_eq!(unsafe { bind_vertex_array(1) }, 2);
+}
+
+#[test]
+fn test_loader() {
+	use std::ffi::{c_void, CStr};
+
+	extern "C-unwind" fn fake_get_version() -> u32 {
+		42
+	}
+
+	fn get_proc_address(name: &CStr) -> *const c_void {
+		match name.to_bytes() {
+			b"getVersion" => fake_get_version as *const c_void,
+			_ => std::ptr::null(),
+		}
+	}
+
+	#[dylink(loader = get_proc_address)]
+	extern "C-unwind" {
+		fn getVersion() -> u32;
+		fn getMissing() -> u32;
+	}
+
+	assert_eq!(unsafe { getVersion() }, 42);
+	assert!(!getMissing_is_available());
+}
+
+#[test]
+fn test_trace() {
+	use dylink::hooks::{self, CallEvent, ResolveEvent, Subscriber};
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	struct Counter {
+		resolves: AtomicUsize,
+		calls: AtomicUsize,
+	}
+
+	impl Subscriber for Counter {
+		fn on_resolve(&self, event: &ResolveEvent) {
+			if event.symbol == "traced" {
+				assert_eq!(event.library, "MOCK");
+				assert!(event.result.is_ok());
+				self.resolves.fetch_add(1, Ordering::Relaxed);
+			}
+		}
+
+		fn on_call(&self, event: &CallEvent) {
+			if event.name == "traced" {
+				self.calls.fetch_add(1, Ordering::Relaxed);
+			}
+		}
+	}
+
+	static MOCK: MockLibrary = MockLibrary::new();
+	static COUNTER: Counter = Counter {
+		resolves: AtomicUsize::new(0),
+		calls: AtomicUsize::new(0),
+	};
+
+	#[dylink(library = MOCK, trace)]
+	extern "C-unwind" fn traced(x: u32) -> u32;
+
+	extern "C-unwind" fn fake_traced(x: u32) -> u32 {
+		x
+	}
+
+	assert!(hooks::set_subscriber(&COUNTER).is_ok());
+	MOCK.insert("traced", fake_traced as *const Symbol);
+	for i in 0..3 {
+		assert_eq!(unsafe { traced(i) }, i);
+	}
+	// the address is cached after the first call
+	assert_eq!(COUNTER.resolves.load(Ordering::Relaxed), 1);
+	assert_eq!(COUNTER.calls.load(Ordering::Relaxed), 3);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_override() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = MOCK)]
+	extern "C-unwind" {
+		fn answer() -> u32;
+	}
+
+	extern "C-unwind" fn real_answer() -> u32 {
+		42
+	}
+	extern "C-unwind" fn fake_answer() -> u32 {
+		0
+	}
+	extern "C-unwind" fn scoped_answer() -> u32 {
+		7
+	}
+
+	MOCK.insert("answer", real_answer as *const Symbol);
+	assert_eq!(unsafe { answer() }, 42);
+	answer_set_override(fake_answer);
+	assert_eq!(unsafe { answer() }, 0);
+	{
+		let _guard = answer_override_with(scoped_answer);
+		assert_eq!(unsafe { answer() }, 7);
+	}
+	assert_eq!(unsafe { answer() }, 0);
+	answer_clear_override();
+	assert_eq!(unsafe { answer() }, 42);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_override_function_pattern() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = MOCK)]
+	extern "C-unwind" fn answer() -> u32;
+
+	struct Widget;
+
+	impl Widget {
+		#[dylink(library = MOCK, link_name = "answer")]
+		extern "C-unwind" fn answer() -> u32;
+	}
+
+	extern "C-unwind" fn real_answer() -> u32 {
+		42
+	}
+	extern "C-unwind" fn fake_answer() -> u32 {
+		0
+	}
+
+	assert!(!answer_is_available());
+	assert!(Widget::answer_ptr().is_none());
+	MOCK.insert("answer", real_answer as *const Symbol);
+	assert!(answer_is_available());
+	assert!(Widget::answer_is_available());
+	{
+		let _guard = answer_override_with(fake_answer);
+		assert_eq!(unsafe { answer() }, 0);
+		assert_eq!(unsafe { Widget::answer() }, 42);
+	}
+	Widget::answer_set_override(fake_answer);
+	assert_eq!(unsafe { answer() }, 42);
+	assert_eq!(unsafe { Widget::answer() }, 0);
+	Widget::answer_clear_override();
+	assert_eq!(unsafe { Widget::answer() }, 42);
+}
+
+#[test]
+fn test_library_expr() {
+	struct Libs {
+		mock: MockLibrary,
+	}
+
+	static LIBS: Libs = Libs {
+		mock: MockLibrary::new(),
+	};
+	static LAZY_LIB: std::sync::LazyLock<MockLibrary> = std::sync::LazyLock::new(MockLibrary::new);
+
+	fn mock() -> &'static MockLibrary {
+		&LIBS.mock
+	}
+
+	#[dylink(library = mock())]
+	extern "C-unwind" {
+		fn from_fn() -> u32;
+	}
+
+	#[dylink(library = &LIBS.mock)]
+	extern "C-unwind" fn from_field() -> u32;
+
+	#[dylink(library = *LAZY_LIB)]
+	extern "C-unwind" {
+		fn from_lazy() -> u32;
+	}
+
+	extern "C-unwind" fn fake() -> u32 {
+		1
+	}
+
+	LIBS.mock.insert("from_fn", fake as *const Symbol);
+	LIBS.mock.insert("from_field", fake as *const Symbol);
+	LAZY_LIB.insert("from_lazy", fake as *const Symbol);
+	assert_eq!(unsafe { from_fn() + from_field() + from_lazy() }, 3);
+}
+
+#[test]
+fn test_name_mapping() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = MOCK, prefix = "vk", case = "camel")]
+	extern "C-unwind" {
+		fn create_instance() -> u32;
+	}
+
+	#[dylink(library = MOCK, prefix = "SDL_", case = "pascal", suffix = "_EXT")]
+	extern "C-unwind" {
+		fn get_ticks() -> u32;
+		#[dylink(fallback = fake)]
+		fn get_error() -> u32;
+	}
+
+	extern "C-unwind" fn fake() -> u32 {
+		3
+	}
+
+	MOCK.insert("vkCreateInstance", fake as *const Symbol);
+	MOCK.insert("SDL_GetTicks_EXT", fake as *const Symbol);
+	assert_eq!(unsafe { create_instance() + get_ticks() }, 6);
+	assert!(!get_error_is_available());
+}
+
+#[test]
+fn test_item_attributes() {
+	static CORE: MockLibrary = MockLibrary::new();
+	static EXT: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = CORE)]
+	extern "C-unwind" {
+		fn core_fn() -> u32;
+		#[link_name = "renamed"]
+		fn standard_rename() -> u32;
+		#[dylink(library = EXT, link_name = "extFn")]
+		fn ext_fn() -> u32;
+	}
+
+	extern "C-unwind" fn one() -> u32 {
+		1
+	}
+
+	CORE.insert("core_fn", one as *const Symbol);
+	CORE.insert("renamed", one as *const Symbol);
+	EXT.insert("extFn", one as *const Symbol);
+	assert_eq!(unsafe { core_fn() + standard_rename() + ext_fn() }, 3);
+}
+
+#[test]
+fn test_safe_fn() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[dylink(library = MOCK, try_fn)]
+	unsafe extern "C-unwind" {
+		safe fn safe_abs(x: i32) -> i32;
+		unsafe fn unsafe_abs(x: i32) -> i32;
+	}
+
+	extern "C-unwind" fn fake_abs(x: i32) -> i32 {
+		x.abs()
+	}
+
+	MOCK.insert("safe_abs", fake_abs as *const Symbol);
+	MOCK.insert("unsafe_abs", fake_abs as *const Symbol);
+	assert_eq!(safe_abs(-3), 3);
+	assert_eq!(try_safe_abs(-4).unwrap(), 4);
+	assert_eq!(unsafe { unsafe_abs(-5) }, 5);
+	let pfn: Option<PFN_safe_abs> = safe_abs_ptr();
+	assert_eq!(pfn.map(|pfn| pfn(-6)), Some(6));
+}
+
+#[test]
+fn test_cpp_mangling() {
+	static MOCK: MockLibrary = MockLibrary::new();
+
+	#[repr(C)]
+	struct Widget;
+
+	#[dylink(library = MOCK)]
+	extern "C-unwind" {
+		#[dylink(cpp = "ns::Widget::resize(int, float) const")]
+		fn resize(this: *const Widget, width: i32, scale: f32);
+		#[dylink(cpp = "swap(Foo*, Foo*)")]
+		fn swap(a: *mut Widget, b: *mut Widget);
+		#[dylink(cpp = "ns::f(ns::Widget const&)")]
+		fn f(widget: *const Widget);
+		#[dylink(cpp = "std::foo(const char*, unsigned long long)")]
+		fn foo(s: *const std::ffi::c_char, n: u64);
+		#[dylink(cpp = "bar()")]
+		fn bar();
+	}
+
+	extern "C-unwind" fn stub() {}
+
+	for name in [
+		"_ZNK2ns6Widget6resizeEif",
+		"_Z4swapP3FooS0_",
+		"_ZN2ns1fERKNS_6WidgetE",
+		"_ZSt3fooPKcy",
+		"_Z3barv",
+	] {
+		MOCK.insert(name, stub as *const Symbol);
+	}
+	assert!(resize_is_available());
+	assert!(swap_is_available());
+	assert!(f_is_available());
+	assert!(foo_is_available());
+	assert!(bar_is_available());
+}
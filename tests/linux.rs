@@ -27,6 +27,7 @@ fn test_linux_x11() {
 }
 
 #[test]
+#[allow(clippy::manual_c_str_literals)]
 fn test_atoi_linux() {
 	use std::ffi::{c_char, c_int};
 	static THIS: sync::LibLock = sync::LibLock::new(&[]);
@@ -53,3 +54,324 @@ fn test_path() {
 	let path = lib.to_image().unwrap().path();
 	assert!(path.is_ok())
 }
+
+#[test]
+fn test_reload() {
+	use std::ffi::{c_char, c_int};
+	static LIBC: sync::LibLock = sync::LibLock::new(&["libc.so.6"]).reloadable();
+	#[dylink(library = LIBC)]
+	extern "C-unwind" {
+		fn atoi(s: *const c_char) -> c_int;
+	}
+
+	assert_eq!(unsafe { atoi(c"5".as_ptr()) }, 5);
+	LIBC.reload().unwrap();
+	assert_eq!(unsafe { atoi(c"6".as_ptr()) }, 6);
+
+	static NOT_RELOADABLE: sync::LibLock = sync::LibLock::new(&["libc.so.6"]);
+	assert!(NOT_RELOADABLE.reload().is_err());
+}
+
+#[test]
+fn test_reload_waits_for_calls() {
+	use std::ffi::{c_int, c_void};
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::{thread, time::Duration};
+
+	static LIBC: sync::LibLock = sync::LibLock::new(&["libc.so.6"]).reloadable();
+	#[dylink(library = LIBC)]
+	extern "C-unwind" {
+		fn qsort(
+			base: *mut c_void,
+			n: usize,
+			size: usize,
+			compar: extern "C" fn(*const c_void, *const c_void) -> c_int,
+		);
+	}
+
+	static IN_CALL: AtomicBool = AtomicBool::new(false);
+	static RELOADED: AtomicBool = AtomicBool::new(false);
+	static RELOADED_DURING_CALL: AtomicBool = AtomicBool::new(false);
+
+	// keeps `qsort` in flight long enough for a reload to be attempted.
+	extern "C" fn compare(a: *const c_void, b: *const c_void) -> c_int {
+		IN_CALL.store(true, Ordering::SeqCst);
+		thread::sleep(Duration::from_millis(200));
+		RELOADED_DURING_CALL.store(RELOADED.load(Ordering::SeqCst), Ordering::SeqCst);
+		unsafe { (*a.cast::<c_int>()).cmp(&*b.cast::<c_int>()) as c_int }
+	}
+
+	let call = thread::spawn(|| {
+		let mut values: [c_int; 2] = [2, 1];
+		unsafe { qsort(values.as_mut_ptr().cast(), 2, size_of::<c_int>(), compare) };
+		values
+	});
+	while !IN_CALL.load(Ordering::SeqCst) {
+		thread::yield_now();
+	}
+	LIBC.reload().unwrap();
+	RELOADED.store(true, Ordering::SeqCst);
+
+	assert_eq!(call.join().unwrap(), [1, 2]);
+	assert!(!RELOADED_DURING_CALL.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_cached_symbols() {
+	static LIBC: sync::LibLock = sync::LibLock::new(&["libc.so.6"]);
+	assert!(LIBC.cached_symbols().is_empty());
+
+	let first = LIBC.symbol("atoi").unwrap();
+	let second = LIBC.symbol("atoi").unwrap();
+	assert_eq!(first, second);
+	LIBC.symbol("strlen").unwrap();
+	assert!(LIBC.symbol("dylink_does_not_exist").is_err());
+
+	let mut cached = LIBC.cached_symbols();
+	cached.sort();
+	let names: Vec<&str> = cached.iter().map(|(name, _)| name.as_str()).collect();
+	assert_eq!(names, ["atoi", "strlen"]);
+	assert_eq!(cached[0].1, first);
+}
+
+#[test]
+fn test_with_loader() {
+	use std::ffi::{c_char, c_int};
+	static LIBC: sync::LibLock = sync::LibLock::with_loader(|| Library::open("libc.so.6"));
+	#[dylink(library = LIBC)]
+	extern "C-unwind" {
+		fn atoi(s: *const c_char) -> c_int;
+	}
+
+	assert_eq!(unsafe { atoi(c"7".as_ptr()) }, 7);
+
+	static MISSING: sync::LibLock =
+		sync::LibLock::with_loader(|| Err(std::io::ErrorKind::NotFound.into()));
+	assert!(MISSING.symbol("atoi").is_err());
+	assert_eq!(MISSING.state(), sync::LoadState::Failed);
+}
+
+#[test]
+fn test_preload() {
+	static LIBC: sync::LibLock = sync::LibLock::new(&["libc.so.6"]);
+	LIBC.preload();
+	LIBC.wait().unwrap();
+	assert!(LIBC.is_ready());
+	assert!(LIBC.symbol("atoi").is_ok());
+
+	static MISSING: sync::LibLock = sync::LibLock::new(&["libdoesnotexist.so"]);
+	MISSING.preload();
+	assert!(MISSING.wait().is_err());
+	assert!(!MISSING.is_ready());
+}
+
+#[test]
+fn test_on_load() {
+	static LIB: sync::LibLock = sync::LibLock::new(&["libc.so.6", "libX11.so.6"])
+		.on_load(|lib: &Library| lib.symbol("XOpenDisplay").map(drop));
+	LIB.wait().unwrap();
+	assert!(LIB.symbol("XOpenDisplay").is_ok());
+
+	static REJECTED: sync::LibLock = sync::LibLock::new(&["libc.so.6"])
+		.on_load(|_: &Library| Err(std::io::Error::other("too old")));
+	let err = REJECTED.wait().unwrap_err();
+	assert_eq!(err.to_string(), "too old");
+}
+
+#[test]
+fn test_loaded_candidate() {
+	static LIB: sync::LibLock = sync::LibLock::new(&["libdoesnotexist.so", "libX11.so.6"]);
+	assert_eq!(LIB.state(), sync::LoadState::Uninit);
+	assert!(LIB.loaded_candidate().is_none());
+
+	LIB.wait().unwrap();
+	assert_eq!(LIB.state(), sync::LoadState::Loaded);
+	let (index, path) = LIB.loaded_candidate().unwrap();
+	assert_eq!(index, 1);
+	assert_eq!(path, std::path::Path::new("libX11.so.6"));
+}
+
+#[test]
+fn test_reentrant() {
+	static LIBC: sync::LibLock =
+		sync::LibLock::new(&["libc.so.6"]).on_load(|_: &Library| LIBC.symbol("atoi").map(drop));
+
+	let err = LIBC.wait().unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+	let reentrant = err
+		.get_ref()
+		.and_then(|err| err.downcast_ref::<sync::ReentrantError>())
+		.unwrap();
+	assert_eq!(reentrant.library(), "libc.so.6");
+	assert_eq!(LIBC.state(), sync::LoadState::Failed);
+}
+
+#[test]
+fn test_reentrant_reload() {
+	use std::ffi::{c_char, c_int};
+	use std::sync::atomic::{AtomicBool, Ordering};
+
+	static RELOADING: AtomicBool = AtomicBool::new(false);
+	static LIBC: sync::LibLock = sync::LibLock::new(&["libc.so.6"])
+		.reloadable()
+		.on_load(reenter);
+
+	#[dylink(library = LIBC, try_fn)]
+	extern "C-unwind" {
+		fn atoi(s: *const c_char) -> c_int;
+	}
+
+	// calls back into the lock, but only while it is being reloaded.
+	fn reenter(_: &Library) -> std::io::Result<()> {
+		if RELOADING.load(Ordering::SeqCst) {
+			let err = unsafe { try_atoi(c"1".as_ptr()) }.unwrap_err();
+			assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+			LIBC.symbol("atoi")?;
+		}
+		Ok(())
+	}
+
+	assert_eq!(unsafe { atoi(c"5".as_ptr()) }, 5);
+	RELOADING.store(true, Ordering::SeqCst);
+	let err = LIBC.reload().unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+	assert!(err
+		.get_ref()
+		.is_some_and(|err| err.is::<sync::ReentrantError>()));
+	assert_eq!(LIBC.state(), sync::LoadState::Failed);
+
+	RELOADING.store(false, Ordering::SeqCst);
+	LIBC.reload().unwrap();
+	assert_eq!(unsafe { atoi(c"6".as_ptr()) }, 6);
+}
+
+#[test]
+fn test_variadic() {
+	use std::ffi::{c_char, c_int, CStr};
+
+	static LIBC: sync::LibLock = sync::LibLock::new(&["libc.so.6"]).reloadable();
+
+	#[dylink(library = LIBC)]
+	extern "C" {
+		fn snprintf(s: *mut c_char, n: usize, format: *const c_char, ...) -> c_int;
+	}
+
+	let mut buf = [0 as c_char; 16];
+	let len = unsafe {
+		snprintf()(
+			buf.as_mut_ptr(),
+			buf.len(),
+			c"%d-%s".as_ptr(),
+			42 as c_int,
+			c"ok".as_ptr(),
+		)
+	};
+	assert_eq!(len, 5);
+	assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }, c"42-ok");
+	assert!(snprintf_is_available());
+
+	LIBC.reload().unwrap();
+	let len = unsafe { snprintf()(buf.as_mut_ptr(), buf.len(), c"%x".as_ptr(), 255 as c_int) };
+	assert_eq!(len, 2);
+	assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }, c"ff");
+}
+
+#[test]
+fn test_static_link() {
+	use std::ffi::{c_char, c_int};
+
+	static LIBC: sync::LibLock = sync::LibLock::new(&["libc.so.6"]);
+
+	#[dylink(library = LIBC, static_link = cfg(unix), static_lib = "c", try_fn)]
+	extern "C" {
+		fn atoi(s: *const c_char) -> c_int;
+		fn snprintf(s: *mut c_char, n: usize, format: *const c_char, ...) -> c_int;
+	}
+
+	#[dylink(library = LIBC, static_link = cfg(not(unix)))]
+	extern "C" {
+		#[link_name = "atoi"]
+		fn dynamic_atoi(s: *const c_char) -> c_int;
+	}
+
+	assert_eq!(unsafe { atoi(c"12".as_ptr()) }, 12);
+	assert!(atoi_is_available());
+	let pfn: PFN_atoi = atoi_ptr().unwrap();
+	assert_eq!(unsafe { pfn(c"56".as_ptr()) }, 56);
+	assert_eq!(unsafe { try_atoi(c"78".as_ptr()) }.unwrap(), 78);
+	let mut buf = [0 as c_char; 8];
+	assert_eq!(
+		unsafe { snprintf()(buf.as_mut_ptr(), buf.len(), c"%d".as_ptr(), 9) },
+		1
+	);
+	assert!(snprintf_ptr().is_some());
+	#[cfg(feature = "testing")]
+	{
+		extern "C" fn fake_atoi(_: *const c_char) -> c_int {
+			42
+		}
+		let _guard = atoi_override_with(fake_atoi);
+		assert_eq!(unsafe { atoi(c"12".as_ptr()) }, 42);
+	}
+	assert_eq!(unsafe { dynamic_atoi(c"34".as_ptr()) }, 34);
+	assert!(dynamic_atoi_is_available());
+	assert_eq!(LIBC.state(), sync::LoadState::Loaded);
+}
+
+#[test]
+fn test_errno_preserved() {
+	use std::ffi::c_int;
+
+	// the missing candidate makes loading set `errno`.
+	static LIBC: sync::LibLock = sync::LibLock::new(&["libdylink_missing.so", "libc.so.6"]);
+
+	#[dylink(library = LIBC)]
+	extern "C" {
+		fn __errno_location() -> *mut c_int;
+	}
+
+	extern "C" {
+		#[link_name = "__errno_location"]
+		fn libc_errno() -> *mut c_int;
+	}
+
+	unsafe {
+		*libc_errno() = 1234;
+		assert_eq!(*__errno_location(), 1234);
+	}
+}
+
+#[test]
+fn test_errno_preserved_reloadable() {
+	use std::ffi::{c_char, c_int, c_long};
+
+	// calls through reloadable locks are tracked before and after the function runs.
+	static LIBC: sync::LibLock = sync::LibLock::new(&["libc.so.6"]).reloadable();
+
+	#[dylink(library = LIBC)]
+	extern "C" {
+		fn abs(x: c_int) -> c_int;
+		fn strtol(s: *const c_char, end: *mut *mut c_char, base: c_int) -> c_long;
+	}
+
+	extern "C" {
+		#[link_name = "__errno_location"]
+		fn libc_errno() -> *mut c_int;
+	}
+
+	unsafe {
+		// the first call loads the library and starts tracking calls on this thread.
+		*libc_errno() = 1234;
+		assert_eq!(abs(-1), 1);
+		assert_eq!(*libc_errno(), 1234);
+
+		// errors set by the function survive the end of the call.
+		*libc_errno() = 0;
+		strtol(
+			c"99999999999999999999999".as_ptr(),
+			std::ptr::null_mut(),
+			10,
+		);
+		assert_eq!(*libc_errno(), 34);
+	}
+}
@@ -10,8 +10,11 @@ fn test_macro() {
 		fn SetLastError(_: u32);
 	}
 
+	// a separate library, so `GetLastError` loads it on its first call, after the error is set.
+	static KERNEL32_LAZY: sync::LibLock = sync::LibLock::new(&["Kernel32.dll"]);
+
 	// macro output: function
-	#[dylink(library = KERNEL32)]
+	#[dylink(library = KERNEL32_LAZY)]
 	extern "system-unwind" fn GetLastError() -> u32;
 
 	unsafe {